@@ -27,6 +27,7 @@ import {
 import { Slider } from "@/components/ui/slider";
 import type { ExplorationNode } from "@/hooks/usePathfinder";
 import { type Bounds, findCachedAzimuthBoundsContaining, preloadDEM } from "@/lib/dem-cache";
+import { isRawRaster, parseRawRaster } from "@/lib/raw-raster";
 import { formatSlope, gradientToSlopeAngle, slopeAngleToGradient } from "@/lib/utils";
 import { hoverIndexStore as defaultHoverIndexStore, slopeUnitStore } from "@/store";
 import { saveAs } from "file-saver";
@@ -505,22 +506,22 @@ export default function PathFinderPage() {
     async (azimuths: Uint8Array, gradients: Uint8Array, runoutZones?: Uint8Array) => {
       console.log('[Raster] handleSetAspectRaster called, runoutZones:', runoutZones?.length ?? 'undefined');
       
-      const azimuthRaster = (await parseGeoraster(
-        azimuths.buffer as ArrayBuffer
-      )) as GeoRaster;
+      // Layers are raw raster buffers from the worker, or GeoTIFFs from older cache entries
+      const parseLayer = async (layer: Uint8Array): Promise<GeoRaster> =>
+        isRawRaster(layer)
+          ? (parseRawRaster(layer) as unknown as GeoRaster)
+          : ((await parseGeoraster(layer.buffer as ArrayBuffer)) as GeoRaster);
 
-      const gradientRaster = (await parseGeoraster(
-        gradients.buffer as ArrayBuffer
-      )) as GeoRaster;
+      const azimuthRaster = await parseLayer(azimuths);
+
+      const gradientRaster = await parseLayer(gradients);
 
       const rastersToMerge = [azimuthRaster, gradientRaster];
       
       // Add runout zones raster if provided and has data
       if (runoutZones && runoutZones.length > 0) {
         console.log('[Raster] Parsing runout zones raster');
-        const runoutRaster = (await parseGeoraster(
-          runoutZones.buffer as ArrayBuffer
-        )) as GeoRaster;
+        const runoutRaster = await parseLayer(runoutZones);
         rastersToMerge.push(runoutRaster);
         console.log('[Raster] Runout raster added, bands now:', rastersToMerge.length);
       }
@@ -512,21 +512,30 @@ const FindPathButton = forwardRef<HTMLButtonElement, FindPathButtonProps>(
             };
             
             worker.addEventListener("message", handler);
+            // Copy the cached layers once and transfer the copies, so the cache stays attached
+            const elevationsBuffer = new Uint8Array(azimuthData.elevations);
+            const azimuthsBuffer = new Uint8Array(azimuthData.azimuths);
+            const gradientsBuffer = new Uint8Array(azimuthData.gradients);
+            const runoutZonesBuffer = avoidRunoutZones && azimuthData.runout_zones ? new Uint8Array(azimuthData.runout_zones) : undefined;
+            const transfer = [elevationsBuffer, azimuthsBuffer, gradientsBuffer, runoutZonesBuffer]
+              .filter((buffer): buffer is Uint8Array => buffer !== undefined)
+              .map((buffer) => buffer.buffer as ArrayBuffer);
             worker.postMessage({
               type: "find_path",
               id,
-              elevationsBuffer: new Uint8Array(azimuthData.elevations),
+              elevationsBuffer,
               start: waypoints[i].coordinates as [number, number],
               end: waypoints[i + 1].coordinates as [number, number],
               maxGradient,
-              azimuthsBuffer: new Uint8Array(azimuthData.azimuths),
+              azimuthsBuffer,
               excludedAspects,
-              gradientsBuffer: new Uint8Array(azimuthData.gradients),
-              aspectGradientThreshold: 0.05,
+              gradientsBuffer,
+              // Avoid excluded aspects on anything steeper than ~0.8° (tan(0.8°) ≈ 0.014); gradients are true rise/run
+              aspectGradientThreshold: 0.014,
               explorationBatchSize,
               explorationDelayMs,
-              runoutZonesBuffer: avoidRunoutZones && azimuthData.runout_zones ? new Uint8Array(azimuthData.runout_zones) : undefined,
-            } as WorkerRequest);
+              runoutZonesBuffer,
+            } as WorkerRequest, transfer);
           });
           
           try {
@@ -311,8 +311,8 @@ class SmoothRasterOverlay extends L.Layer {
         
         if (intensity > 0 && gradient >= RED_GRADIENT_THRESHOLD) {
           // Apply gradient-based opacity with smooth falloff from aspect boundaries
-          // Use higher minimum opacity to prevent gaps at lower gradients
-          const baseOpacity = Math.max(0.25, Math.min(gradient * 0.5, 0.8));
+          // Use higher minimum opacity to prevent gaps at lower gradients, reaching full opacity around 25°
+          const baseOpacity = Math.max(0.25, Math.min(gradient * 1.75, 0.8));
           const opacity = baseOpacity * intensity;
 
           data[pixelIndex] = 255;     // Red
@@ -0,0 +1,71 @@
+import { describe, expect, it } from "vitest";
+import { isRawRaster, parseRawRaster } from "./raw-raster";
+
+/**
+ * Encode samples the way serialize_to_raw does, with no GeoKeys or CRS params
+ */
+function encodeRaw(samples: number[], width: number, height: number, noData: number | null): Uint8Array {
+  const dataOffset = 88;
+  const bytes = new Uint8Array(dataOffset + samples.length * 4);
+  const view = new DataView(bytes.buffer);
+  bytes.set([0x50, 0x46, 0x52, 0x52]); // PFRR
+  view.setUint16(4, 1, true);
+  view.setUint16(6, noData === null ? 0 : 1, true);
+  view.setUint32(8, dataOffset, true);
+  view.setUint32(12, width, true);
+  view.setUint32(16, height, true);
+  [500000, 30, 0, 4000000, 0, -30].forEach((value, i) => view.setFloat64(20 + i * 8, value, true));
+  view.setFloat64(68, noData ?? 0, true);
+  samples.forEach((value, i) => view.setFloat32(dataOffset + i * 4, value, true));
+  return bytes;
+}
+
+describe("isRawRaster", () => {
+  it("recognises the PFRR magic", () => {
+    expect(isRawRaster(encodeRaw([1], 1, 1, null))).toBe(true);
+  });
+
+  it("rejects TIFF headers and short buffers", () => {
+    expect(isRawRaster(new Uint8Array([0x49, 0x49, 0x2a, 0x00]))).toBe(false);
+    expect(isRawRaster(new Uint8Array([0x50, 0x46]))).toBe(false);
+  });
+});
+
+describe("parseRawRaster", () => {
+  it("reads the grid and rows of samples", () => {
+    const raster = parseRawRaster(encodeRaw([1, 2, 3, 4, 5, 6], 3, 2, null));
+    expect(raster.width).toBe(3);
+    expect(raster.height).toBe(2);
+    expect(raster.xmin).toBe(500000);
+    expect(raster.xmax).toBe(500090);
+    expect(raster.ymax).toBe(4000000);
+    expect(raster.ymin).toBe(3999940);
+    expect(raster.pixelHeight).toBe(30);
+    expect(Array.from(raster.values[0][1])).toEqual([4, 5, 6]);
+  });
+
+  it("views the samples in place when aligned", () => {
+    const bytes = encodeRaw([1, 2], 2, 1, null);
+    const raster = parseRawRaster(bytes);
+    expect(raster.values[0][0].buffer).toBe(bytes.buffer);
+  });
+
+  it("copies the samples when the buffer is misaligned", () => {
+    const encoded = encodeRaw([7, 8], 2, 1, null);
+    const padded = new Uint8Array(encoded.length + 1);
+    padded.set(encoded, 1);
+    const raster = parseRawRaster(padded.subarray(1));
+    expect(Array.from(raster.values[0][0])).toEqual([7, 8]);
+  });
+
+  it("leaves nodata out of the value range", () => {
+    const raster = parseRawRaster(encodeRaw([-9999, 2, 10, -9999], 2, 2, -9999));
+    expect(raster.noDataValue).toBe(-9999);
+    expect(raster.mins).toEqual([2]);
+    expect(raster.maxs).toEqual([10]);
+  });
+
+  it("rejects truncated buffers", () => {
+    expect(() => parseRawRaster(encodeRaw([1, 2, 3, 4], 2, 2, null).subarray(0, 90))).toThrow();
+  });
+});
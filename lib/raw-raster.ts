@@ -0,0 +1,94 @@
+/**
+ * Raw raster buffers from the WASM module (`compute_azimuths_raw`): a small little-endian header followed by
+ * Float32 samples. See pathfinder/src/raw.rs for the layout.
+ */
+
+const MAGIC = "PFRR";
+const HAS_NODATA = 1;
+
+/**
+ * Single-band raster in the shape of the georaster fields the map layers read
+ */
+export interface RawRaster {
+  width: number;
+  height: number;
+  xmin: number;
+  xmax: number;
+  ymin: number;
+  ymax: number;
+  pixelWidth: number;
+  pixelHeight: number;
+  noDataValue: number | null;
+  numberOfRasters: 1;
+  /** One band of rows, each a Float32Array view of the buffer where alignment allows */
+  values: Float32Array[][];
+  mins: number[];
+  maxs: number[];
+  ranges: number[];
+}
+
+/**
+ * True if the buffer starts with the raw raster magic rather than a TIFF header
+ */
+export function isRawRaster(bytes: Uint8Array): boolean {
+  return bytes.length >= 4 && String.fromCharCode(bytes[0], bytes[1], bytes[2], bytes[3]) === MAGIC;
+}
+
+/**
+ * Decode a raw raster buffer. Rows view the buffer's samples in place, so keep the buffer alive and don't
+ * transfer it while the raster is in use.
+ */
+export function parseRawRaster(bytes: Uint8Array): RawRaster {
+  if (!isRawRaster(bytes)) {
+    throw new Error("Missing PFRR magic");
+  }
+  const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  const flags = view.getUint16(6, true);
+  const dataOffset = view.getUint32(8, true);
+  const width = view.getUint32(12, true);
+  const height = view.getUint32(16, true);
+  // Transform in GDAL order: origin x, pixel width, row rotation, origin y, column rotation, pixel height
+  const originX = view.getFloat64(20, true);
+  const pixelWidth = view.getFloat64(28, true);
+  const originY = view.getFloat64(44, true);
+  const pixelHeight = Math.abs(view.getFloat64(60, true));
+  const noDataValue = flags & HAS_NODATA ? view.getFloat64(68, true) : null;
+
+  if (dataOffset + width * height * 4 > bytes.byteLength) {
+    throw new Error(`Expected ${width}x${height} samples at byte ${dataOffset}`);
+  }
+  // Float32Array views need 4-byte alignment; copy the samples out only if the buffer doesn't allow it
+  const start = bytes.byteOffset + dataOffset;
+  const samples =
+    start % 4 === 0
+      ? new Float32Array(bytes.buffer, start, width * height)
+      : new Float32Array(bytes.slice(dataOffset, dataOffset + width * height * 4).buffer);
+  const rows = Array.from({ length: height }, (_, row) => samples.subarray(row * width, (row + 1) * width));
+
+  // Samples are Float32, so compare them with the nodata value rounded the same way
+  const noDataSample = noDataValue === null ? null : Math.fround(noDataValue);
+  let min = Number.POSITIVE_INFINITY;
+  let max = Number.NEGATIVE_INFINITY;
+  for (const value of samples) {
+    if (value === noDataSample || Number.isNaN(value)) continue;
+    if (value < min) min = value;
+    if (value > max) max = value;
+  }
+
+  return {
+    width,
+    height,
+    xmin: originX,
+    xmax: originX + width * pixelWidth,
+    ymin: originY - height * pixelHeight,
+    ymax: originY,
+    pixelWidth,
+    pixelHeight,
+    noDataValue,
+    numberOfRasters: 1,
+    values: [rows],
+    mins: [min],
+    maxs: [max],
+    ranges: [max - min],
+  };
+}
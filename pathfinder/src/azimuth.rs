@@ -1,38 +1,78 @@
-use georaster::geotiff::GeoTiffReader;
 use serde::{Deserialize, Serialize};
-use std::{f64::consts::PI, io::Cursor};
-use wasm_bindgen::prelude::*;
+use std::{f64::consts::PI, str::FromStr};
+
+use crate::{
+  classify::rate_terrain,
+  curvature::{compute_curvatures, Curvatures},
+  error::PathfinderError,
+  raster::Raster,
+  runout::{RunoutConfig, RunoutModel, RunoutTracer},
+};
+
+/// Nodata value of the derived layers wherever the elevation raster has voids.
+/// It lies outside the range of azimuths, gradients and runout intensities.
+pub const DERIVED_NODATA: f64 = -9999.0;
+
+/// Derived terrain layers computed from an elevation raster
+pub struct TerrainAnalysis {
+  pub azimuths: Raster<f64>,
+  pub gradients: Raster<f64>,
+  pub runout_zones: Raster<f64>,
+  pub curvatures: Curvatures,
+  /// `AtesRating` codes
+  pub ates_ratings: Raster<f64>,
+}
 
-use crate::{get_raster, serialize_to_geotiff};
+/// The layers routing needs, from `analyze_slopes`
+pub struct SlopeAnalysis {
+  pub azimuths: Raster<f64>,
+  pub gradients: Raster<f64>,
+  pub runout_zones: Raster<f64>,
+}
 
-#[wasm_bindgen]
-pub struct AzimuthResult {
-  elevations: Vec<u8>,
-  azimuths: Vec<u8>,
-  gradients: Vec<u8>,
-  runout_zones: Vec<u8>,
+/// Choices made by `analyze_terrain_with_options`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AnalysisOptions {
+  pub algorithm: SlopeAlgorithm,
+  pub runout_model: RunoutModel,
+  pub runout_config: RunoutConfig,
 }
 
-#[wasm_bindgen]
-impl AzimuthResult {
-  #[wasm_bindgen(getter)]
-  pub fn elevations(&self) -> Vec<u8> {
-    self.elevations.clone()
-  }
+/// Method for estimating the surface gradient at each pixel. Every method yields rise/run, comparable with
+/// GDAL and QGIS slope rasters.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlopeAlgorithm {
+  /// Horn (1981) weighted 3x3 differences, the GDAL and QGIS default
+  Horn,
+  /// Zevenbergen and Thorne (1987) central differences of the four edge neighbours; sharpest on 1 m lidar
+  ZevenbergenThorne,
+  /// Evans-Young unweighted 3x3 quadratic surface fit
+  EvansYoung,
+  /// Weighted 5x5 Sobel differences, smoothing noise on coarse data
+  #[default]
+  Sobel5x5,
+}
 
-  #[wasm_bindgen(getter)]
-  pub fn azimuths(&self) -> Vec<u8> {
-    self.azimuths.clone()
+impl SlopeAlgorithm {
+  /// Antisymmetric kernel estimating the east-west gradient; its transpose gives the north-south one
+  pub(crate) fn kernel(&self) -> &'static [[f64; 5]; 5] {
+    match self {
+      SlopeAlgorithm::Horn => &HORN_KERNEL,
+      SlopeAlgorithm::ZevenbergenThorne => &ZEVENBERGEN_THORNE_KERNEL,
+      SlopeAlgorithm::EvansYoung => &EVANS_YOUNG_KERNEL,
+      SlopeAlgorithm::Sobel5x5 => &SOBEL_5X5_KERNEL,
+    }
   }
+}
 
-  #[wasm_bindgen(getter)]
-  pub fn gradients(&self) -> Vec<u8> {
-    self.gradients.clone()
-  }
+impl FromStr for SlopeAlgorithm {
+  type Err = PathfinderError;
 
-  #[wasm_bindgen(getter)]
-  pub fn runout_zones(&self) -> Vec<u8> {
-    self.runout_zones.clone()
+  /// Parse a kebab-case name such as `horn` or `zevenbergen-thorne`
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase()))
+      .map_err(|_| PathfinderError::UnknownSlopeAlgorithm { name: name.to_string() })
   }
 }
 
@@ -55,7 +95,7 @@ impl Aspect {
     if azimuth == -1.0 {
       Aspect::Flat
     } else {
-      match azimuth as f64 {
+      match azimuth {
         a if a < 22.5 => Aspect::North,
         a if a < 67.5 => Aspect::Northeast,
         a if a < 112.5 => Aspect::East,
@@ -95,7 +135,7 @@ pub fn calculate_azimuth(gx: f64, gy: f64) -> f64 {
   }
 
   // Calculate azimuth in radians, then convert to degrees
-  let azimuth_radians: f64 = ((-gx) as f64).atan2(gy as f64); // Invert gx to correct E/W mapping
+  let azimuth_radians: f64 = (-gx).atan2(gy); // Invert gx to correct E/W mapping
   let mut azimuth_degrees: f64 = azimuth_radians * 180.0 / PI;
 
   // Normalize to [0, 360)
@@ -103,352 +143,376 @@ pub fn calculate_azimuth(gx: f64, gy: f64) -> f64 {
     azimuth_degrees += 360.0;
   }
 
-  azimuth_degrees as f64
+  azimuth_degrees
 }
 
-/// Compute gradient along azimuth
+/// Compute gradient along azimuth from elevation change per metre in x and y
 fn compute_gradient_along_azimuth(gx: f64, gy: f64, azimuth: f64) -> f64 {
   if azimuth == -1.0 {
     return 0.0;
   }
 
-  const PIXEL_SIZE: f64 = 10.0; // 10m pixel size
-  const KERNEL_SUM: f64 = 68.0; // Sum of absolute values in Sobel 5x5 kernel
-
-  // Normalize gradients
-  let gx_normalized: f64 = gx / (KERNEL_SUM * PIXEL_SIZE).abs();
-  let gy_normalized: f64 = gy / (KERNEL_SUM * PIXEL_SIZE).abs();
-
   // Calculate slope as rise/run
-  ((gx_normalized * gx_normalized) + (gy_normalized * gy_normalized)).sqrt()
+  ((gx * gx) + (gy * gy)).sqrt()
 }
 
-/// Compute D8 flow directions for each cell.
-/// Returns a 2D array where each value encodes the direction to the steepest downhill neighbor:
-///   0=N, 1=NE, 2=E, 3=SE, 4=S, 5=SW, 6=W, 7=NW, 255=flat/sink (no downhill neighbor)
-fn compute_d8_flow_directions(elevations: &Vec<Vec<f64>>) -> Vec<Vec<u8>> {
-  let height = elevations.len();
-  let width = elevations[0].len();
-  
-  let mut flow_dir: Vec<Vec<u8>> = vec![vec![255; width]; height];
-  
-  // D8 neighbor offsets: (dy, dx) for directions 0-7
-  // Direction encoding: 0=N, 1=NE, 2=E, 3=SE, 4=S, 5=SW, 6=W, 7=NW
-  const D8_OFFSETS: [(isize, isize); 8] = [
-    (-1, 0),  // 0: N
-    (-1, 1),  // 1: NE
-    (0, 1),   // 2: E
-    (1, 1),   // 3: SE
-    (1, 0),   // 4: S
-    (1, -1),  // 5: SW
-    (0, -1),  // 6: W
-    (-1, -1), // 7: NW
-  ];
-  
-  // Distance weights for diagonal vs cardinal (sqrt(2) vs 1)
-  const D8_WEIGHTS: [f64; 8] = [1.0, 1.414, 1.0, 1.414, 1.0, 1.414, 1.0, 1.414];
-  
-  for i in 1..(height - 1) {
-    for j in 1..(width - 1) {
-      let center_elev = elevations[i][j];
-      let mut steepest_slope = 0.0;
-      let mut steepest_dir: u8 = 255;
-      
-      for (dir, &(dy, dx)) in D8_OFFSETS.iter().enumerate() {
-        let ny = (i as isize + dy) as usize;
-        let nx = (j as isize + dx) as usize;
-        
-        let neighbor_elev = elevations[ny][nx];
-        let drop = center_elev - neighbor_elev;
-        
-        if drop > 0.0 {
-          // Slope = drop / distance (accounting for diagonal distance)
-          let slope = drop / D8_WEIGHTS[dir];
-          if slope > steepest_slope {
-            steepest_slope = slope;
-            steepest_dir = dir as u8;
-          }
-        }
-      }
-      
-      flow_dir[i][j] = steepest_dir;
-    }
-  }
-  
-  flow_dir
+/// True if (x, y) is steep enough to release an avalanche and faces one of the `excluded_aspects`
+pub(crate) fn is_start_zone(
+  azimuths: &Raster<f64>,
+  gradients: &Raster<f64>,
+  excluded_aspects: &[Aspect],
+  config: &RunoutConfig,
+  x: usize,
+  y: usize,
+) -> bool {
+  !gradients.is_nodata(x, y)
+    && gradients[(x, y)] >= config.start_zone_gradient
+    && excluded_aspects.iter().any(|aspect| aspect.contains_azimuth(azimuths[(x, y)], Some(config.aspect_tolerance)))
+}
+
+/// Start zone flags of every cell by index, as `is_start_zone` finds them
+pub(crate) fn start_zones(
+  azimuths: &Raster<f64>,
+  gradients: &Raster<f64>,
+  excluded_aspects: &[Aspect],
+  config: &RunoutConfig,
+) -> Vec<bool> {
+  let width: usize = gradients.width();
+  (0..width * gradients.height())
+    .map(|index| is_start_zone(azimuths, gradients, excluded_aspects, config, index % width, index / width))
+    .collect()
 }
 
-/// Compute avalanche runout zones using D8 flow routing.
+/// Compute avalanche runout zones with `model`, by default D8 flow routing, tuned by `config`.
 /// Source zones are steep pixels (gradient >= threshold) with aspect in excluded_aspects.
 /// Returns intensity values (0.0-1.0) that fade with distance from source zones.
 /// Runout zones are the FLAT areas (<10°) below source zones where debris comes to rest.
-fn compute_runout_zones(
-  elevations: &Vec<Vec<f64>>,
-  azimuths: &Vec<Vec<f64>>,
-  gradients: &Vec<Vec<f64>>,
+/// Only this step depends on the excluded aspects, so it can be rerun on existing azimuth and gradient layers.
+pub fn compute_runout_zones(
+  elevations: &Raster<f64>,
+  azimuths: &Raster<f64>,
+  gradients: &Raster<f64>,
   excluded_aspects: &[Aspect],
-) -> Vec<Vec<f64>> {
-  // Minimum gradient to be considered a potential avalanche start zone (~10° slope)
-  // This matches where red aspect shading stops
-  const START_ZONE_THRESHOLD: f64 = 0.176; // tan(10°)
-  // Maximum cells to mark as runout on flat terrain
-  const MAX_RUNOUT_CELLS: usize = 50;
-  // Starting intensity for runout zones (will fade with distance)
-  const INITIAL_INTENSITY: f64 = 1.0;
-  // Decay rate per cell on flat terrain (faster decay since terrain is flat)
-  const DECAY_RATE: f64 = 0.92;
-
-  let height = elevations.len();
-  let width = elevations[0].len();
-  
-  let mut runout: Vec<Vec<f64>> = vec![vec![0.0; width]; height];
+  model: RunoutModel,
+  config: &RunoutConfig,
+) -> Raster<f64> {
+  let mut runout: Raster<f64> = elevations.like(0.0);
+  if elevations.nodata().is_some() {
+    runout.set_nodata(Some(DERIVED_NODATA));
+    for (value, &elevation) in runout.data_mut().iter_mut().zip(elevations.data()) {
+      if elevations.is_nodata_value(elevation) {
+        *value = DERIVED_NODATA;
+      }
+    }
+  }
   
   // If no aspects are excluded, no runout zones to compute
   if excluded_aspects.is_empty() {
     return runout;
   }
+
+  let start_zones: Vec<bool> = start_zones(azimuths, gradients, excluded_aspects, config);
+  let sources: Vec<usize> = (0..start_zones.len()).filter(|&index| start_zones[index]).collect();
+  let mut tracer = RunoutTracer::new(elevations, gradients, start_zones, model, config);
+  tracer.trace(&sources);
+  for (index, intensity) in tracer.reached() {
+    runout.data_mut()[index] = intensity;
+  }
   
-  // Compute D8 flow directions
-  let flow_dir = compute_d8_flow_directions(elevations);
-  
-  // D8 neighbor offsets matching direction encoding
-  const D8_OFFSETS: [(isize, isize); 8] = [
-    (-1, 0),  // 0: N
-    (-1, 1),  // 1: NE
-    (0, 1),   // 2: E
-    (1, 1),   // 3: SE
-    (1, 0),   // 4: S
-    (1, -1),  // 5: SW
-    (0, -1),  // 6: W
-    (-1, -1), // 7: NW
-  ];
-  
-  // Find all source zone cells and propagate runout from each
-  // Also mark source zone cells with low-intensity runout to blend with red shading
-  for i in 1..(height - 1) {
-    for j in 1..(width - 1) {
-      let gradient = gradients[i][j];
-      let azimuth = azimuths[i][j];
-      
-      // Must be steep enough to be an avalanche start zone
-      if gradient < START_ZONE_THRESHOLD {
-        continue;
-      }
-      
-      // Check if this pixel's aspect is in the excluded list
-      let mut is_excluded = false;
-      for aspect in excluded_aspects {
-        if aspect.contains_azimuth(azimuth, Some(22.5)) {
-          is_excluded = true;
-          break;
-        }
-      }
-      
-      if !is_excluded {
+  runout
+}
+
+// East-west gradient kernels, embedded in a 5x5 window; 3x3 kernels leave the outer ring zero
+const HORN_KERNEL: [[f64; 5]; 5] = [
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, -2.0, 0.0, 2.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+];
+
+const ZEVENBERGEN_THORNE_KERNEL: [[f64; 5]; 5] = [
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+];
+
+const EVANS_YOUNG_KERNEL: [[f64; 5]; 5] = [
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, -1.0, 0.0, 1.0, 0.0],
+  [0.0, 0.0, 0.0, 0.0, 0.0],
+];
+
+const SOBEL_5X5_KERNEL: [[f64; 5]; 5] = [
+  [-5.0, -4.0, 0.0, 4.0, 5.0],
+  [-8.0, -10.0, 0.0, 10.0, 8.0],
+  [-10.0, -20.0, 0.0, 20.0, 10.0],
+  [-8.0, -10.0, 0.0, 10.0, 8.0],
+  [-5.0, -4.0, 0.0, 4.0, 5.0],
+];
+
+/// Pair of kernel cells `offset` apart either side of `middle`. If one side is missing, falls back to the
+/// one-sided difference from `middle` at half the moment. Returns the weighted difference and its moment.
+fn mirrored_pair(
+  weight: f64,
+  offset: f64,
+  near: Option<f64>,
+  middle: Option<f64>,
+  far: Option<f64>,
+) -> Option<(f64, f64)> {
+  let moment: f64 = weight.abs() * offset;
+  match (near, middle, far) {
+    (Some(near), _, Some(far)) => Some((weight * (near - far), moment)),
+    (Some(near), Some(middle), None) => Some((weight * (near - middle), moment / 2.0)),
+    (None, Some(middle), Some(far)) => Some((weight * (middle - far), moment / 2.0)),
+    _ => None,
+  }
+}
+
+/// Elevation change per pixel east (gx) and south (gy) at (x, y), from `kernel` and its transpose.
+/// Each kernel is applied as pairs of cells mirrored across the center; cells outside the raster are
+/// treated like nodata. A pair with one missing cell falls back to a one-sided difference from the middle
+/// of its row or column, pairs with nothing usable are dropped, and the rest are normalized by their
+/// moment, so a planar surface yields the same (gx, gy) at edges and voids as anywhere else.
+/// Returns `None` if either kernel has no usable pair left.
+pub(crate) fn surface_gradient(elevations: &Raster<f64>, x: usize, y: usize, kernel: &[[f64; 5]; 5]) -> Option<(f64, f64)> {
+  let value = |ki: usize, kj: usize| -> Option<f64> {
+    let vx: usize = (x + kj).checked_sub(2).filter(|&vx| vx < elevations.width())?;
+    let vy: usize = (y + ki).checked_sub(2).filter(|&vy| vy < elevations.height())?;
+    let v: f64 = elevations[(vx, vy)];
+    (!elevations.is_nodata_value(v)).then_some(v)
+  };
+
+  let (mut gx, mut gx_moment) = (0.0, 0.0);
+  let (mut gy, mut gy_moment) = (0.0, 0.0);
+  for (a, row) in kernel.iter().enumerate() {
+    for (b, &weight) in row[..2].iter().enumerate() {
+      if weight == 0.0 {
         continue;
       }
-      
-      // Mark source zone cells near the 10° threshold with fading runout
-      // to create a smooth blend between red aspect shading and amber runout
-      // The closer to 10° threshold, the more runout blending we apply
-      let blend_range = 0.35 - START_ZONE_THRESHOLD; // ~10° to ~20° range for blending
-      let gradient_above_threshold = gradient - START_ZONE_THRESHOLD;
-      if gradient_above_threshold < blend_range {
-        let blend_factor = 1.0 - (gradient_above_threshold / blend_range);
-        let edge_intensity = blend_factor * 0.5; // Max 50% intensity at the 10° edge
-        runout[i][j] = runout[i][j].max(edge_intensity);
+      let offset: f64 = (2 - b) as f64;
+
+      // gx pairs mirror columns b and 4 - b within row a
+      if let Some((response, moment)) = mirrored_pair(weight, offset, value(a, b), value(a, 2), value(a, 4 - b)) {
+        gx += response;
+        gx_moment += moment;
       }
-      
-      // This is a source zone - follow D8 flow and mark runout with fading intensity
-      let mut current_y = i;
-      let mut current_x = j;
-      let mut runout_cells = 0;
-      let mut current_intensity = INITIAL_INTENSITY;
-      
-      // Follow flow and mark runout starting from first cell after source
-      loop {
-        let dir = flow_dir[current_y][current_x];
-        
-        // Stop if this is a sink (no downhill flow)
-        if dir == 255 {
-          break;
-        }
-        
-        // Move to next cell following flow direction
-        let (dy, dx) = D8_OFFSETS[dir as usize];
-        let next_y = (current_y as isize + dy) as usize;
-        let next_x = (current_x as isize + dx) as usize;
-        
-        // Bounds check
-        if next_y == 0 || next_y >= height - 1 || next_x == 0 || next_x >= width - 1 {
-          break;
-        }
-        
-        current_y = next_y;
-        current_x = next_x;
-        runout_cells += 1;
-        
-        // Decay intensity with distance
-        current_intensity *= DECAY_RATE;
-        
-        // Don't mark cells that are themselves steep excluded-aspect source zones (they show as red)
-        let next_gradient = gradients[current_y][current_x];
-        let next_azimuth = azimuths[current_y][current_x];
-        let mut next_is_source = false;
-        if next_gradient >= START_ZONE_THRESHOLD {
-          for aspect in excluded_aspects {
-            if aspect.contains_azimuth(next_azimuth, Some(22.5)) {
-              next_is_source = true;
-              break;
-            }
-          }
-        }
-        
-        // Only mark as runout if it's not a source zone itself (source zones show as red)
-        // Use max to accumulate intensity from multiple flow paths
-        if !next_is_source {
-          runout[current_y][current_x] = runout[current_y][current_x].max(current_intensity);
-        }
-        
-        // Stop conditions:
-        // 1. Traveled max distance
-        // 2. Intensity has faded too much
-        // Note: We continue on flat terrain - runout extends until it fades out
-        if runout_cells >= MAX_RUNOUT_CELLS {
-          break;
-        }
-        if current_intensity < 0.05 {
-          break;
-        }
+
+      // gy pairs mirror rows b and 4 - b within column a
+      if let Some((response, moment)) = mirrored_pair(weight, offset, value(b, a), value(2, a), value(4 - b, a)) {
+        gy += response;
+        gy_moment += moment;
       }
     }
   }
-  
-  // Lateral spreading pass: expand runout zones to fill gaps between D8 flow paths
-  // This simulates debris spreading laterally as it flows downhill
-  const SPREAD_ITERATIONS: usize = 2;
-  const SPREAD_DECAY: f64 = 0.7; // Intensity multiplier for spread cells
-  
-  for _ in 0..SPREAD_ITERATIONS {
-    let mut spread_runout = runout.clone();
-    
-    for i in 1..(height - 1) {
-      for j in 1..(width - 1) {
-        if runout[i][j] > 0.0 {
-          // Spread to 4-connected neighbors (not diagonal, to avoid over-spreading)
-          let neighbors = [(i - 1, j), (i + 1, j), (i, j - 1), (i, j + 1)];
-          
-          for &(ny, nx) in &neighbors {
-            if ny > 0 && ny < height - 1 && nx > 0 && nx < width - 1 {
-              // Don't spread into steep excluded-aspect source zones (they show as red)
-              let neighbor_gradient = gradients[ny][nx];
-              let neighbor_azimuth = azimuths[ny][nx];
-              let mut is_source = false;
-              if neighbor_gradient >= START_ZONE_THRESHOLD {
-                for aspect in excluded_aspects {
-                  if aspect.contains_azimuth(neighbor_azimuth, Some(22.5)) {
-                    is_source = true;
-                    break;
-                  }
-                }
-              }
-              
-              if !is_source {
-                let spread_intensity = runout[i][j] * SPREAD_DECAY;
-                spread_runout[ny][nx] = spread_runout[ny][nx].max(spread_intensity);
-              }
-            }
-          }
-        }
+
+  if gx_moment == 0.0 || gy_moment == 0.0 {
+    return None;
+  }
+  // A pair's difference spans twice its moment's worth of pixels
+  Some((gx / (2.0 * gx_moment), gy / (2.0 * gy_moment)))
+}
+
+/// `analyze_terrain_with_options` using the default 5x5 Sobel kernel and D8 runout tracing
+pub fn analyze_terrain(elevations: &Raster<f64>, excluded_aspects: &[Aspect]) -> TerrainAnalysis {
+  analyze_terrain_with_options(elevations, excluded_aspects, &AnalysisOptions::default())
+}
+
+/// `analyze_slopes`, plus the surface curvatures and the ATES ratings that depend on them
+pub fn analyze_terrain_with_options(
+  elevations: &Raster<f64>,
+  excluded_aspects: &[Aspect],
+  options: &AnalysisOptions,
+) -> TerrainAnalysis {
+  let SlopeAnalysis { azimuths, gradients, runout_zones } = analyze_slopes(elevations, excluded_aspects, options);
+  let curvatures: Curvatures = compute_curvatures(elevations);
+  let ates_ratings: Raster<f64> = rate_terrain(&gradients, &runout_zones, &curvatures);
+  TerrainAnalysis { azimuths, gradients, runout_zones, curvatures, ates_ratings }
+}
+
+/// Compute azimuth and gradient along azimuth for each pixel with `options.algorithm`, then derive runout
+/// zones below start zones on the excluded aspects with `options.runout_model`. Voids in the elevation raster
+/// are excluded from the kernel windows and are nodata in every derived layer.
+pub fn analyze_slopes(
+  elevations: &Raster<f64>,
+  excluded_aspects: &[Aspect],
+  options: &AnalysisOptions,
+) -> SlopeAnalysis {
+  let height: usize = elevations.height();
+  let width: usize = elevations.width();
+
+  let mut azimuths: Raster<f64> = elevations.like(0.0);
+  let mut gradients: Raster<f64> = elevations.like(0.0);
+  if elevations.nodata().is_some() {
+    azimuths.set_nodata(Some(DERIVED_NODATA));
+    gradients.set_nodata(Some(DERIVED_NODATA));
+    for (index, &elevation) in elevations.data().iter().enumerate() {
+      if elevations.is_nodata_value(elevation) {
+        azimuths.data_mut()[index] = DERIVED_NODATA;
+        gradients.data_mut()[index] = DERIVED_NODATA;
       }
     }
-    
-    runout = spread_runout;
   }
-  
-  runout
-}
 
-/// Apply a 5x5 Sobel filter to compute azimuth and gradient along azimuth for each pixel on a `Vec<f32>`
-#[wasm_bindgen]
-pub fn compute_azimuths(elevations_geotiff: &[u8], excluded_aspects: JsValue) -> Result<AzimuthResult, JsValue> {
-  // Parse excluded aspects from JS value
-  let excluded_aspects_vec: Vec<Aspect> = if excluded_aspects.is_undefined() || excluded_aspects.is_null() {
-    vec![]
-  } else {
-    serde_wasm_bindgen::from_value(excluded_aspects).unwrap_or(vec![])
-  };
+  // Apply convolution, including the border where the kernel falls back to one-sided differences
+  for i in 0..height {
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(i);
+    for j in 0..width {
+      if elevations.is_nodata(j, i) {
+        continue;
+      }
 
-  let cursor: Cursor<Vec<u8>> = Cursor::new(elevations_geotiff.to_vec());
-  let mut elevations_geotiff: GeoTiffReader<Cursor<Vec<u8>>> =
-    GeoTiffReader::open(cursor)
-      .map_err(|e| JsValue::from_str(&format!("Failed to open GeoTIFF: {:?}", e)))?;
-  let elevations: Vec<Vec<f64>> = get_raster(&mut elevations_geotiff)?;
-
-  let gx_kernel: [[f64; 5]; 5] = [
-    [-5.0, -4.0, 0.0, 4.0, 5.0],
-    [-8.0, -10.0, 0.0, 10.0, 8.0],
-    [-10.0, -20.0, 0.0, 20.0, 10.0],
-    [-8.0, -10.0, 0.0, 10.0, 8.0],
-    [-5.0, -4.0, 0.0, 4.0, 5.0],
-  ];
+      // Only possible next to voids or on a raster one pixel wide or tall
+      let Some((gx, gy)) = surface_gradient(elevations, j, i, options.algorithm.kernel()) else {
+        azimuths.set_nodata(Some(DERIVED_NODATA));
+        gradients.set_nodata(Some(DERIVED_NODATA));
+        azimuths[(j, i)] = DERIVED_NODATA;
+        gradients[(j, i)] = DERIVED_NODATA;
+        continue;
+      };
 
-  let gy_kernel: [[f64; 5]; 5] = [
-    [-5.0, -8.0, -10.0, -8.0, -5.0],
-    [-4.0, -10.0, -20.0, -10.0, -4.0],
-    [0.0, 0.0, 0.0, 0.0, 0.0],
-    [4.0, 10.0, 20.0, 10.0, 4.0],
-    [5.0, 8.0, 10.0, 8.0, 5.0],
-  ];
+      // Scale to elevation change per metre so non-square and geographic pixels get true slopes
+      let (gx, gy) = (gx / spacing_x, gy / spacing_y);
+
+      // Compute azimuth for the current pixel
+      let azimuth: f64 = calculate_azimuth(gx, gy);
+      azimuths[(j, i)] = azimuth;
+      gradients[(j, i)] = compute_gradient_along_azimuth(gx, gy, azimuth);
+    }
+  }
+
+  // Compute runout zones based on excluded aspects
+  let runout_zones = compute_runout_zones(
+    elevations,
+    &azimuths,
+    &gradients,
+    excluded_aspects,
+    options.runout_model,
+    &options.runout_config,
+  );
+
+  SlopeAnalysis {
+    azimuths,
+    gradients,
+    runout_zones,
+  }
+}
 
-  let height: usize = elevations.len();
-  let width: usize = elevations[0].len();
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
 
-  let mut azimuths: Vec<Vec<f64>> = vec![vec![0.0; width]; height];
-  let mut gradients: Vec<Vec<f64>> = vec![vec![0.0; width]; height];
+  const ALGORITHMS: [SlopeAlgorithm; 4] = [
+    SlopeAlgorithm::Horn,
+    SlopeAlgorithm::ZevenbergenThorne,
+    SlopeAlgorithm::EvansYoung,
+    SlopeAlgorithm::Sobel5x5,
+  ];
 
-  // Apply convolution
-  for i in 2..(height - 2) {
-    for j in 2..(width - 2) {
-      let mut gx: f64 = 0.0;
-      let mut gy: f64 = 0.0;
+  /// Plane of 30 m pixels rising `rise_east` and `rise_south` metres per metre
+  fn plane(width: usize, height: usize, rise_east: f64, rise_south: f64) -> Raster<f64> {
+    let transform: GeoTransform = GeoTransform {
+      origin_x: 500_000.0,
+      pixel_width: 30.0,
+      row_rotation: 0.0,
+      origin_y: 5_000_000.0,
+      column_rotation: 0.0,
+      pixel_height: -30.0,
+    };
+    let data: Vec<f64> = (0..width * height)
+      .map(|index| 1000.0 + 30.0 * ((index % width) as f64 * rise_east + (index / width) as f64 * rise_south))
+      .collect();
+    Raster::new(data, width, height, transform, CrsInfo::default())
+  }
 
-      // Apply the 5x5 kernel
-      for ki in 0..5 {
-        for kj in 0..5 {
-          let x: usize = j + kj - 2;
-          let y: usize = i + ki - 2;
-          let pixel_value: f64 = elevations[y][x];
+  fn analyze(elevations: &Raster<f64>, algorithm: SlopeAlgorithm) -> TerrainAnalysis {
+    let options: AnalysisOptions = AnalysisOptions { algorithm, ..Default::default() };
+    analyze_terrain_with_options(elevations, &[], &options)
+  }
 
-          gx += pixel_value * gx_kernel[ki][kj];
-          gy += pixel_value * gy_kernel[ki][kj];
-        }
+  #[test]
+  fn plane_gradient_is_rise_over_run_for_every_algorithm() {
+    let elevations: Raster<f64> = plane(9, 7, 0.3, 0.4);
+    for algorithm in ALGORITHMS {
+      let analysis: TerrainAnalysis = analyze(&elevations, algorithm);
+      for (index, &gradient) in analysis.gradients.data().iter().enumerate() {
+        assert!((gradient - 0.5).abs() < 1e-9, "{algorithm:?} gave {gradient} at cell {index}");
       }
+    }
+  }
 
-      // Compute azimuth for the current pixel
-      let azimuth: f64 = calculate_azimuth(gx, gy);
-      azimuths[i][j] = azimuth;
-      gradients[i][j] = compute_gradient_along_azimuth(gx, gy, azimuth);
+  #[test]
+  fn plane_gradient_holds_next_to_voids() {
+    let mut elevations: Raster<f64> = plane(9, 9, 0.5, 0.0);
+    elevations.set_nodata(Some(-32768.0));
+    elevations[(4, 4)] = -32768.0;
+    for algorithm in ALGORITHMS {
+      let analysis: TerrainAnalysis = analyze(&elevations, algorithm);
+      assert_eq!(analysis.gradients[(4, 4)], DERIVED_NODATA);
+      for (x, y) in [(3, 4), (5, 4), (4, 3), (4, 5), (0, 0), (8, 8)] {
+        let gradient: f64 = analysis.gradients[(x, y)];
+        assert!((gradient - 0.5).abs() < 1e-9, "{algorithm:?} gave {gradient} at ({x}, {y})");
+      }
     }
   }
 
-  // Compute runout zones based on excluded aspects
-  let runout_zones = compute_runout_zones(&elevations, &azimuths, &gradients, &excluded_aspects_vec);
+  #[test]
+  fn plane_rising_east_faces_west() {
+    let analysis: TerrainAnalysis = analyze(&plane(5, 5, 0.2, 0.0), SlopeAlgorithm::default());
+    assert!((analysis.azimuths[(2, 2)] - 270.0).abs() < 1e-9);
+    assert!(Aspect::West.contains_azimuth(analysis.azimuths[(2, 2)], None));
+  }
 
-  let geo_keys: Vec<u32> = elevations_geotiff.geo_keys.as_ref()
-    .ok_or_else(|| JsValue::from_str("Missing geo_keys"))?
-    .clone();
-  let origin: [f64; 2] = elevations_geotiff.origin()
-    .ok_or_else(|| JsValue::from_str("Missing origin"))?;
+  #[test]
+  fn flat_ground_has_no_aspect_or_gradient() {
+    let analysis: TerrainAnalysis = analyze(&plane(5, 5, 0.0, 0.0), SlopeAlgorithm::default());
+    assert!(Aspect::Flat.contains_azimuth(analysis.azimuths[(2, 2)], None));
+    assert_eq!(analysis.gradients[(2, 2)], 0.0);
+  }
 
-  // Serialize all rasters to GeoTIFF format
-  let elevations_geotiff_bytes = serialize_to_geotiff(elevations, &geo_keys, &origin)?;
-  let runout_zones_geotiff_bytes = serialize_to_geotiff(runout_zones, &geo_keys, &origin)?;
-  
-  Ok(AzimuthResult {
-    elevations: elevations_geotiff_bytes,
-    azimuths: serialize_to_geotiff(azimuths, &geo_keys, &origin)?,
-    gradients: serialize_to_geotiff(gradients, &geo_keys, &origin)?,
-    runout_zones: runout_zones_geotiff_bytes,
-  })
+  #[test]
+  fn voids_are_nodata_in_every_derived_layer() {
+    let mut elevations: Raster<f64> = plane(9, 9, 0.8, 0.0);
+    elevations.set_nodata(Some(-32768.0));
+    elevations[(4, 4)] = -32768.0;
+    let analysis: TerrainAnalysis = analyze_terrain(&elevations, &[Aspect::West]);
+    let layers: [(&str, &Raster<f64>); 7] = [
+      ("azimuths", &analysis.azimuths),
+      ("gradients", &analysis.gradients),
+      ("runout zones", &analysis.runout_zones),
+      ("profile curvatures", &analysis.curvatures.profile),
+      ("plan curvatures", &analysis.curvatures.plan),
+      ("total curvatures", &analysis.curvatures.total),
+      ("ATES ratings", &analysis.ates_ratings),
+    ];
+    for (name, layer) in layers {
+      assert_eq!(layer.nodata(), Some(DERIVED_NODATA), "{name}");
+      assert_eq!(layer[(4, 4)], DERIVED_NODATA, "{name}");
+      assert!(!layer.is_nodata(3, 4) && !layer.is_nodata(5, 4), "{name}");
+    }
+  }
+
+  #[test]
+  fn start_zones_begin_at_a_true_ten_degree_slope() {
+    let angles: [f64; 7] = [5.0, 9.9, 10.1, 15.0, 19.0, 20.0, 40.0];
+    let elevations: Raster<f64> = plane(angles.len(), 1, 0.0, 0.0);
+    let azimuths: Raster<f64> = elevations.like(180.0);
+    let gradients: Raster<f64> = elevations.with_data(angles.map(|angle: f64| angle.to_radians().tan()).to_vec());
+    let config = RunoutConfig::default();
+    let start_zones: Vec<bool> =
+      (0..angles.len()).map(|x| is_start_zone(&azimuths, &gradients, &[Aspect::South], &config, x, 0)).collect();
+    assert_eq!(start_zones, [false, false, true, true, true, true, true]);
+
+    // Start zones below about 19° also get faint runout, blending the aspect shading into the runout
+    let runout: Raster<f64> =
+      compute_runout_zones(&elevations, &azimuths, &gradients, &[Aspect::South], RunoutModel::D8Trace, &config);
+    for (x, angle) in angles.into_iter().enumerate().skip(2) {
+      assert_eq!(runout[(x, 0)] > 0.0, angle < 19.3, "{angle}°");
+    }
+  }
 }
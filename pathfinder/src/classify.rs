@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::{azimuth::DERIVED_NODATA, curvature::Curvatures, error::PathfinderError, raster::Raster};
+
+/// Concave curvature (1/m) beyond which a cell counts as a terrain trap: a gully, or the abrupt transition
+/// at the foot of a slope where debris piles up deeply
+const TERRAIN_TRAP_CURVATURE: f64 = 0.01;
+
+/// Slope angle bands used in avalanche terrain assessment.
+/// Raster codes are the discriminants, 1 to 5.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlopeClass {
+  /// Below 25°
+  Gentle = 1,
+  /// 25° to 30°
+  Moderate = 2,
+  /// 30° to 35°, where most slab avalanches start
+  Steep = 3,
+  /// 35° to 45°
+  VerySteep = 4,
+  /// 45° and steeper
+  Extreme = 5,
+}
+
+impl SlopeClass {
+  /// Class of a rise/run gradient
+  pub fn from_gradient(gradient: f64) -> SlopeClass {
+    match gradient.atan().to_degrees() {
+      a if a < 25.0 => SlopeClass::Gentle,
+      a if a < 30.0 => SlopeClass::Moderate,
+      a if a < 35.0 => SlopeClass::Steep,
+      a if a < 45.0 => SlopeClass::VerySteep,
+      _ => SlopeClass::Extreme,
+    }
+  }
+}
+
+/// Simplified Avalanche Terrain Exposure Scale rating of a single cell.
+/// Raster codes are the discriminants, 1 to 3.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AtesRating {
+  /// Low-angle terrain away from avalanche paths
+  Simple = 1,
+  /// Moderate or steep slopes, or runout zones, without terrain traps
+  Challenging = 2,
+  /// Very steep slopes, or steep slopes and runout zones with terrain traps
+  Complex = 3,
+}
+
+impl AtesRating {
+  /// Rating of a raster code, or `None` for nodata
+  pub fn from_code(code: f64) -> Option<AtesRating> {
+    match code as i64 {
+      1 => Some(AtesRating::Simple),
+      2 => Some(AtesRating::Challenging),
+      3 => Some(AtesRating::Complex),
+      _ => None,
+    }
+  }
+}
+
+impl FromStr for AtesRating {
+  type Err = PathfinderError;
+
+  /// Parse `simple`, `challenging` or `complex`
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase()))
+      .map_err(|_| PathfinderError::UnknownAtesRating { name: name.to_string() })
+  }
+}
+
+/// Slope class code of each cell; nodata gradients stay nodata
+pub fn classify_slopes(gradients: &Raster<f64>) -> Raster<f64> {
+  let mut classes: Raster<f64> =
+    gradients.map(|g| if gradients.is_nodata_value(g) { DERIVED_NODATA } else { SlopeClass::from_gradient(g) as u8 as f64 });
+  classes.set_nodata(gradients.nodata());
+  classes
+}
+
+/// ATES rating code of each cell from its slope class, whether it lies in a runout zone, and whether its
+/// profile or plan curvature makes it a terrain trap. Nodata gradients stay nodata.
+pub fn rate_terrain(gradients: &Raster<f64>, runout_zones: &Raster<f64>, curvatures: &Curvatures) -> Raster<f64> {
+  let mut ratings: Raster<f64> = gradients.like(DERIVED_NODATA);
+  ratings.set_nodata(gradients.nodata());
+
+  for y in 0..gradients.height() {
+    for x in 0..gradients.width() {
+      if gradients.is_nodata(x, y) {
+        continue;
+      }
+      let terrain_trap: bool = [&curvatures.profile, &curvatures.plan]
+        .iter()
+        .any(|curvature| !curvature.is_nodata(x, y) && curvature[(x, y)] < -TERRAIN_TRAP_CURVATURE);
+      let in_runout: bool = !runout_zones.is_nodata(x, y) && runout_zones[(x, y)] > 0.0;
+      let exposed_if_trapped = |trapped: AtesRating, open: AtesRating| if terrain_trap { trapped } else { open };
+
+      let rating: AtesRating = match SlopeClass::from_gradient(gradients[(x, y)]) {
+        SlopeClass::VerySteep | SlopeClass::Extreme => AtesRating::Complex,
+        SlopeClass::Steep => exposed_if_trapped(AtesRating::Complex, AtesRating::Challenging),
+        _ if in_runout => exposed_if_trapped(AtesRating::Complex, AtesRating::Challenging),
+        SlopeClass::Moderate => exposed_if_trapped(AtesRating::Challenging, AtesRating::Simple),
+        SlopeClass::Gentle => AtesRating::Simple,
+      };
+      ratings[(x, y)] = rating as u8 as f64;
+    }
+  }
+
+  ratings
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  #[test]
+  fn slope_classes_follow_the_angle_bands() {
+    let class = |degrees: f64| SlopeClass::from_gradient(degrees.to_radians().tan());
+    assert_eq!(class(0.0), SlopeClass::Gentle);
+    assert_eq!(class(24.9), SlopeClass::Gentle);
+    assert_eq!(class(25.1), SlopeClass::Moderate);
+    assert_eq!(class(32.0), SlopeClass::Steep);
+    assert_eq!(class(44.9), SlopeClass::VerySteep);
+    assert_eq!(class(45.1), SlopeClass::Extreme);
+  }
+
+  /// Slope angle, whether in a runout zone, whether a terrain trap, and the expected rating
+  type Case = (f64, bool, bool, AtesRating);
+
+  #[test]
+  fn runout_and_terrain_traps_raise_the_rating() {
+    let cases: [Case; 8] = [
+      (15.0, false, false, AtesRating::Simple),
+      (15.0, false, true, AtesRating::Simple),
+      (15.0, true, false, AtesRating::Challenging),
+      (15.0, true, true, AtesRating::Complex),
+      (27.0, false, false, AtesRating::Simple),
+      (27.0, false, true, AtesRating::Challenging),
+      (32.0, false, false, AtesRating::Challenging),
+      (40.0, false, false, AtesRating::Complex),
+    ];
+    let row = |value: &dyn Fn(&Case) -> f64| -> Raster<f64> {
+      let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+      Raster::new(cases.iter().map(value).collect(), cases.len(), 1, transform, CrsInfo::default())
+    };
+    let gradients: Raster<f64> = row(&|&(degrees, ..)| degrees.to_radians().tan());
+    let runout_zones: Raster<f64> = row(&|&(_, runout, ..)| if runout { 0.5 } else { 0.0 });
+    let profile: Raster<f64> = row(&|&(_, _, trap, _)| if trap { -0.02 } else { 0.0 });
+    let curvatures = Curvatures { plan: profile.like(0.0), total: profile.like(0.0), profile };
+
+    let ratings: Raster<f64> = rate_terrain(&gradients, &runout_zones, &curvatures);
+    for (x, &(degrees, runout, trap, rating)) in cases.iter().enumerate() {
+      assert_eq!(AtesRating::from_code(ratings[(x, 0)]), Some(rating), "{degrees}°, runout {runout}, trap {trap}");
+    }
+  }
+}
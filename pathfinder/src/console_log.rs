@@ -1,5 +1,7 @@
-use web_sys::console;
-
+#[cfg(feature = "wasm")]
 pub(crate) fn console_log(message: &str) {
-  console::log_1(&message.into());
+  web_sys::console::log_1(&message.into());
 }
+
+#[cfg(not(feature = "wasm"))]
+pub(crate) fn console_log(_message: &str) {}
@@ -0,0 +1,145 @@
+use crate::{azimuth::DERIVED_NODATA, raster::Raster};
+
+/// Surface curvature rasters in 1/m, positive where the terrain is convex and negative where it is concave
+pub struct Curvatures {
+  /// Curvature along the fall line: positive on convex rolls, negative at the foot of slopes and in terrain traps
+  pub profile: Raster<f64>,
+  /// Tangential curvature across the fall line: positive on divergent ridges and spurs, negative in convergent
+  /// gullies
+  pub plan: Raster<f64>,
+  /// Negative Laplacian of the surface, combining both directions
+  pub total: Raster<f64>,
+}
+
+/// 3x3 elevation window centered on (x, y), rows running north to south. Missing edge neighbours (outside
+/// the raster or on voids) are reflected through the center (2e - opposite), so that direction contributes no
+/// curvature, and missing corners are extrapolated from the plane through their two edge neighbours.
+/// Returns `None` if both neighbours along a row or column are missing.
+fn window(elevations: &Raster<f64>, x: usize, y: usize) -> Option<[[f64; 3]; 3]> {
+  let value = |row: usize, col: usize| -> Option<f64> {
+    let vx: usize = (x + col).checked_sub(1).filter(|&vx| vx < elevations.width())?;
+    let vy: usize = (y + row).checked_sub(1).filter(|&vy| vy < elevations.height())?;
+    let v: f64 = elevations[(vx, vy)];
+    (!elevations.is_nodata_value(v)).then_some(v)
+  };
+
+  let center: f64 = elevations[(x, y)];
+  let mut cells: [[f64; 3]; 3] = [[center; 3]; 3];
+  for (row, col) in [(0, 1), (1, 0), (1, 2), (2, 1)] {
+    cells[row][col] = match value(row, col) {
+      Some(v) => v,
+      None => 2.0 * center - value(2 - row, 2 - col)?,
+    };
+  }
+  for (row, col) in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+    cells[row][col] = value(row, col).unwrap_or(cells[row][1] + cells[1][col] - center);
+  }
+  Some(cells)
+}
+
+/// Profile, plan (tangential) and total curvature at (x, y) from the Zevenbergen and Thorne (1987) quadratic
+/// surface
+fn curvature_at(elevations: &Raster<f64>, x: usize, y: usize) -> Option<[f64; 3]> {
+  let w: [[f64; 3]; 3] = window(elevations, x, y)?;
+  let [lx, ly] = elevations.pixel_spacing(y);
+  let center: f64 = w[1][1];
+
+  // First and second derivatives, with y pointing north
+  let p: f64 = (w[1][2] - w[1][0]) / (2.0 * lx);
+  let q: f64 = (w[0][1] - w[2][1]) / (2.0 * ly);
+  let r: f64 = (w[1][0] + w[1][2] - 2.0 * center) / (lx * lx);
+  let t: f64 = (w[0][1] + w[2][1] - 2.0 * center) / (ly * ly);
+  let s: f64 = (w[0][2] - w[0][0] - w[2][2] + w[2][0]) / (4.0 * lx * ly);
+
+  let total: f64 = -(r + t);
+  let slope_squared: f64 = p * p + q * q;
+  if slope_squared == 0.0 {
+    // No fall line on flat ground
+    return Some([0.0, 0.0, total]);
+  }
+  let profile: f64 =
+    -(r * p * p + 2.0 * s * p * q + t * q * q) / (slope_squared * (1.0 + slope_squared).powf(1.5));
+  let plan: f64 = -(t * p * p - 2.0 * s * p * q + r * q * q) / (slope_squared * (1.0 + slope_squared).sqrt());
+  Some([profile, plan, total])
+}
+
+/// Compute profile, plan and total curvature for each pixel.
+/// Voids in the elevation raster are nodata in every curvature raster.
+pub fn compute_curvatures(elevations: &Raster<f64>) -> Curvatures {
+  let mut profile: Raster<f64> = elevations.like(0.0);
+  let mut plan: Raster<f64> = elevations.like(0.0);
+  let mut total: Raster<f64> = elevations.like(0.0);
+  if elevations.nodata().is_some() {
+    for layer in [&mut profile, &mut plan, &mut total] {
+      layer.set_nodata(Some(DERIVED_NODATA));
+    }
+  }
+
+  for y in 0..elevations.height() {
+    for x in 0..elevations.width() {
+      let values: [f64; 3] = if elevations.is_nodata(x, y) {
+        [DERIVED_NODATA; 3]
+      } else {
+        match curvature_at(elevations, x, y) {
+          Some(values) => values,
+          // Only possible next to voids or on a raster one pixel wide or tall
+          None => {
+            for layer in [&mut profile, &mut plan, &mut total] {
+              layer.set_nodata(Some(DERIVED_NODATA));
+            }
+            [DERIVED_NODATA; 3]
+          }
+        }
+      };
+      profile[(x, y)] = values[0];
+      plan[(x, y)] = values[1];
+      total[(x, y)] = values[2];
+    }
+  }
+
+  Curvatures { profile, plan, total }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  /// 9x9 grid of 30 m pixels whose elevation is `surface` of the east and north offsets in metres from the
+  /// centre pixel
+  fn grid(surface: impl Fn(f64, f64) -> f64) -> Raster<f64> {
+    let data: Vec<f64> = (0..81)
+      .map(|index| surface(((index % 9) as f64 - 4.0) * 30.0, (4.0 - (index / 9) as f64) * 30.0))
+      .collect();
+    Raster::new(data, 9, 9, GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]), CrsInfo::default())
+  }
+
+  #[test]
+  fn plane_has_no_curvature_up_to_the_edges() {
+    let curvatures: Curvatures = compute_curvatures(&grid(|east, north| 1000.0 + 0.3 * east - 0.2 * north));
+    for layer in [&curvatures.profile, &curvatures.plan, &curvatures.total] {
+      assert!(layer.data().iter().all(|value| value.abs() < 1e-12), "{:?}", layer.data());
+    }
+  }
+
+  #[test]
+  fn bowl_floor_is_concave() {
+    let k: f64 = 0.001;
+    let curvatures: Curvatures = compute_curvatures(&grid(|east, north| 1000.0 + k * (east * east + north * north)));
+    assert!((curvatures.total[(4, 4)] + 4.0 * k).abs() < 1e-12);
+    // No fall line at the bottom
+    assert_eq!(curvatures.profile[(4, 4)], 0.0);
+    assert_eq!(curvatures.plan[(4, 4)], 0.0);
+  }
+
+  #[test]
+  fn valley_converges_across_the_fall_line() {
+    let (k, slope): (f64, f64) = (0.001, 0.4);
+    let curvatures: Curvatures = compute_curvatures(&grid(|east, north| 1000.0 + k * east * east + slope * north));
+    assert!((curvatures.plan[(4, 4)] + 2.0 * k / (1.0 + slope * slope).sqrt()).abs() < 1e-12);
+    assert!(curvatures.profile[(4, 4)].abs() < 1e-12);
+    // A ridge is the same shape upside down
+    let ridge: Curvatures = compute_curvatures(&grid(|east, north| 1000.0 - k * east * east + slope * north));
+    assert!((ridge.plan[(4, 4)] + curvatures.plan[(4, 4)]).abs() < 1e-12);
+  }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+
+use georaster::Coordinate;
+
+/// Errors raised by the terrain analysis and routing pipeline.
+/// Each variant has a stable machine-readable `code` that callers can branch on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathfinderError {
+  /// The input could not be opened or decoded as a TIFF
+  InvalidGeoTiff { message: String },
+  /// A raw raster buffer is malformed
+  InvalidRawRaster { message: String },
+  /// The image has no width/height
+  MissingDimensions,
+  /// The image has no tie points, pixel scale or model transformation
+  MissingGeoreference,
+  /// The image has no GeoKeyDirectory, so its CRS is unknown
+  MissingGeoKeys,
+  /// The sample type can't be read as elevation data
+  UnsupportedSampleFormat { found: String },
+  /// A derived layer does not share the elevation raster's grid
+  GridMismatch { layer: &'static str },
+  /// A multi-band GeoTIFF has no band with this description
+  MissingBand { band: &'static str },
+  /// Writing a GeoTIFF failed
+  GeoTiffWrite { message: String },
+  /// A point could not be parsed as a GeoJSON Point geometry
+  InvalidGeoJson { message: String },
+  /// The name does not match any `SlopeAlgorithm`
+  UnknownSlopeAlgorithm { name: String },
+  /// The name does not match any `AtesRating`
+  UnknownAtesRating { name: String },
+  /// The name does not match any `FlowRouting`
+  UnknownFlowRouting { name: String },
+  /// The name does not match any `RunoutModel`
+  UnknownRunoutModel { name: String },
+  /// A runout alpha angle outside (0°, 90°)
+  InvalidAlphaAngle { alpha: f64 },
+  /// A runout configuration could not be deserialized
+  InvalidRunoutConfig { message: String },
+  /// Excluded aspects are not a list of aspect names
+  InvalidExcludedAspects { message: String },
+  /// Routing options could not be deserialized
+  InvalidPathOptions { message: String },
+  /// A route start outside the elevation raster
+  StartOutOfBounds { coordinate: Coordinate },
+  /// A route end outside the elevation raster
+  EndOutOfBounds { coordinate: Coordinate },
+  /// A watershed pour point outside the elevation raster
+  PourPointOutOfBounds { coordinate: Coordinate },
+  /// The start, end or pour point falls on a nodata cell of the elevation raster
+  NoDataAtEndpoint { coordinate: Coordinate },
+  /// The search space was exhausted without reaching the goal
+  UnreachableGoal,
+}
+
+impl PathfinderError {
+  pub fn code(&self) -> &'static str {
+    match self {
+      PathfinderError::InvalidGeoTiff { .. } => "INVALID_GEOTIFF",
+      PathfinderError::InvalidRawRaster { .. } => "INVALID_RAW_RASTER",
+      PathfinderError::MissingDimensions => "MISSING_DIMENSIONS",
+      PathfinderError::MissingGeoreference => "MISSING_GEOREFERENCE",
+      PathfinderError::MissingGeoKeys => "MISSING_GEO_KEYS",
+      PathfinderError::UnsupportedSampleFormat { .. } => "UNSUPPORTED_SAMPLE_FORMAT",
+      PathfinderError::GridMismatch { .. } => "GRID_MISMATCH",
+      PathfinderError::MissingBand { .. } => "MISSING_BAND",
+      PathfinderError::GeoTiffWrite { .. } => "GEOTIFF_WRITE_FAILED",
+      PathfinderError::InvalidGeoJson { .. } => "INVALID_GEOJSON",
+      PathfinderError::UnknownSlopeAlgorithm { .. } => "UNKNOWN_SLOPE_ALGORITHM",
+      PathfinderError::UnknownAtesRating { .. } => "UNKNOWN_ATES_RATING",
+      PathfinderError::UnknownFlowRouting { .. } => "UNKNOWN_FLOW_ROUTING",
+      PathfinderError::UnknownRunoutModel { .. } => "UNKNOWN_RUNOUT_MODEL",
+      PathfinderError::InvalidAlphaAngle { .. } => "INVALID_ALPHA_ANGLE",
+      PathfinderError::InvalidRunoutConfig { .. } => "INVALID_RUNOUT_CONFIG",
+      PathfinderError::InvalidExcludedAspects { .. } => "INVALID_EXCLUDED_ASPECTS",
+      PathfinderError::InvalidPathOptions { .. } => "INVALID_PATH_OPTIONS",
+      PathfinderError::StartOutOfBounds { .. } => "START_OUT_OF_BOUNDS",
+      PathfinderError::EndOutOfBounds { .. } => "END_OUT_OF_BOUNDS",
+      PathfinderError::PourPointOutOfBounds { .. } => "POUR_POINT_OUT_OF_BOUNDS",
+      PathfinderError::NoDataAtEndpoint { .. } => "NODATA_AT_ENDPOINT",
+      PathfinderError::UnreachableGoal => "UNREACHABLE_GOAL",
+    }
+  }
+}
+
+impl fmt::Display for PathfinderError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PathfinderError::InvalidGeoTiff { message } => write!(f, "Failed to open GeoTIFF: {}", message),
+      PathfinderError::InvalidRawRaster { message } => write!(f, "Invalid raw raster: {}", message),
+      PathfinderError::MissingDimensions => write!(f, "Failed to get image dimensions"),
+      PathfinderError::MissingGeoreference => write!(f, "Missing origin or pixel size"),
+      PathfinderError::MissingGeoKeys => write!(f, "Missing geo_keys"),
+      PathfinderError::UnsupportedSampleFormat { found } => write!(f, "Unsupported sample format: {}", found),
+      PathfinderError::GridMismatch { layer } => write!(f, "The {} raster does not match the elevations grid", layer),
+      PathfinderError::MissingBand { band } => write!(f, "The GeoTIFF has no {} band", band),
+      PathfinderError::GeoTiffWrite { message } => write!(f, "{}", message),
+      PathfinderError::InvalidGeoJson { message } => write!(f, "Invalid GeoJSON: {}", message),
+      PathfinderError::UnknownSlopeAlgorithm { name } => write!(f, "Unknown slope algorithm: {}", name),
+      PathfinderError::UnknownAtesRating { name } => write!(f, "Unknown ATES rating: {}", name),
+      PathfinderError::UnknownFlowRouting { name } => write!(f, "Unknown flow routing: {}", name),
+      PathfinderError::UnknownRunoutModel { name } => write!(f, "Unknown runout model: {}", name),
+      PathfinderError::InvalidAlphaAngle { alpha } => {
+        write!(f, "Alpha angle must be between 0 and 90 degrees, got {}", alpha)
+      }
+      PathfinderError::InvalidRunoutConfig { message } => write!(f, "Invalid runout configuration: {}", message),
+      PathfinderError::InvalidExcludedAspects { message } => write!(f, "Invalid excluded aspects: {}", message),
+      PathfinderError::InvalidPathOptions { message } => write!(f, "Invalid path options: {}", message),
+      PathfinderError::StartOutOfBounds { coordinate } => {
+        write!(f, "Start ({}, {}) is outside the elevation raster", coordinate.x, coordinate.y)
+      }
+      PathfinderError::EndOutOfBounds { coordinate } => {
+        write!(f, "End ({}, {}) is outside the elevation raster", coordinate.x, coordinate.y)
+      }
+      PathfinderError::PourPointOutOfBounds { coordinate } => {
+        write!(f, "Pour point ({}, {}) is outside the elevation raster", coordinate.x, coordinate.y)
+      }
+      PathfinderError::NoDataAtEndpoint { coordinate } => {
+        write!(f, "({}, {}) has no elevation data", coordinate.x, coordinate.y)
+      }
+      PathfinderError::UnreachableGoal => write!(f, "No path found"),
+    }
+  }
+}
+
+impl std::error::Error for PathfinderError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashSet;
+
+  #[test]
+  fn every_variant_has_its_own_stable_code() {
+    let coordinate = Coordinate { x: 500_000.0, y: 4_000_000.0 };
+    let message = || "bad".to_string();
+    let errors: [(PathfinderError, &str); 23] = [
+      (PathfinderError::InvalidGeoTiff { message: message() }, "INVALID_GEOTIFF"),
+      (PathfinderError::InvalidRawRaster { message: message() }, "INVALID_RAW_RASTER"),
+      (PathfinderError::MissingDimensions, "MISSING_DIMENSIONS"),
+      (PathfinderError::MissingGeoreference, "MISSING_GEOREFERENCE"),
+      (PathfinderError::MissingGeoKeys, "MISSING_GEO_KEYS"),
+      (PathfinderError::UnsupportedSampleFormat { found: message() }, "UNSUPPORTED_SAMPLE_FORMAT"),
+      (PathfinderError::GridMismatch { layer: "gradients" }, "GRID_MISMATCH"),
+      (PathfinderError::MissingBand { band: "elevations" }, "MISSING_BAND"),
+      (PathfinderError::GeoTiffWrite { message: message() }, "GEOTIFF_WRITE_FAILED"),
+      (PathfinderError::InvalidGeoJson { message: message() }, "INVALID_GEOJSON"),
+      (PathfinderError::UnknownSlopeAlgorithm { name: message() }, "UNKNOWN_SLOPE_ALGORITHM"),
+      (PathfinderError::UnknownAtesRating { name: message() }, "UNKNOWN_ATES_RATING"),
+      (PathfinderError::UnknownFlowRouting { name: message() }, "UNKNOWN_FLOW_ROUTING"),
+      (PathfinderError::UnknownRunoutModel { name: message() }, "UNKNOWN_RUNOUT_MODEL"),
+      (PathfinderError::InvalidAlphaAngle { alpha: 95.0 }, "INVALID_ALPHA_ANGLE"),
+      (PathfinderError::InvalidRunoutConfig { message: message() }, "INVALID_RUNOUT_CONFIG"),
+      (PathfinderError::InvalidExcludedAspects { message: message() }, "INVALID_EXCLUDED_ASPECTS"),
+      (PathfinderError::InvalidPathOptions { message: message() }, "INVALID_PATH_OPTIONS"),
+      (PathfinderError::StartOutOfBounds { coordinate }, "START_OUT_OF_BOUNDS"),
+      (PathfinderError::EndOutOfBounds { coordinate }, "END_OUT_OF_BOUNDS"),
+      (PathfinderError::PourPointOutOfBounds { coordinate }, "POUR_POINT_OUT_OF_BOUNDS"),
+      (PathfinderError::NoDataAtEndpoint { coordinate }, "NODATA_AT_ENDPOINT"),
+      (PathfinderError::UnreachableGoal, "UNREACHABLE_GOAL"),
+    ];
+    for (error, code) in &errors {
+      assert_eq!(error.code(), *code, "{error}");
+    }
+    let codes: HashSet<&str> = errors.iter().map(|(error, _)| error.code()).collect();
+    assert_eq!(codes.len(), errors.len());
+  }
+
+  #[test]
+  fn messages_name_the_offending_value() {
+    let coordinate = Coordinate { x: 1.5, y: -2.0 };
+    assert_eq!(
+      PathfinderError::EndOutOfBounds { coordinate }.to_string(),
+      "End (1.5, -2) is outside the elevation raster"
+    );
+    assert_eq!(PathfinderError::UnknownRunoutModel { name: "bogus".into() }.to_string(), "Unknown runout model: bogus");
+  }
+}
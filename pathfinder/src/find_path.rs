@@ -1,28 +1,117 @@
-use std::{cell::RefCell, collections::HashSet, f64::consts::E, io::Cursor, rc::Rc};
+use std::{cell::RefCell, collections::HashSet, f64::consts::E, rc::Rc};
 
-use geojson::{FeatureCollection, GeoJson, Geometry, Value};
-use georaster::{geotiff::GeoTiffReader, Coordinate};
-use js_sys::Function;
+use geojson::{FeatureCollection, Geometry, Value};
+use georaster::Coordinate;
 use pathfinding::directed::fringe::fringe;
-use wasm_bindgen::prelude::*;
-use crate::{azimuth::Aspect, console_log::console_log, raster::get_raster};
-
-fn parse_point_to_coordinate(point_str: &str) -> Result<Coordinate, JsValue> {
-  let geojson: GeoJson = GeoJson::from_json_value(point_str.parse().unwrap())
-    .map_err(|_| JsValue::from_str("Invalid GeoJSON"))?;
-
-  match geojson {
-    GeoJson::Geometry(Geometry {
-      value: Value::Point(coords),
-      ..
-    }) => Ok(Coordinate::new(coords[1], coords[0])),
-    _ => Err(JsValue::from_str("Invalid point GeoJSON")),
+use serde::Deserialize;
+use crate::{
+  azimuth::Aspect,
+  classify::AtesRating,
+  console_log::console_log,
+  error::PathfinderError,
+  hydrology::DEFAULT_STREAM_THRESHOLD_AREA,
+  overhead::{OverheadExposure, OverheadHazard},
+  raster::{GeoTransform, Raster},
+};
+
+/// Routing constraints for `find_path`.
+/// Omitted fields deserialize to the defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PathOptions {
+  /// Steepest uphill rise/run allowed between neighboring pixels
+  pub max_gradient: f64,
+  /// Aspects to avoid where the terrain is steeper than `aspect_gradient_threshold`
+  pub excluded_aspects: Vec<Aspect>,
+  pub aspect_gradient_threshold: f64,
+  /// Most severe ATES rating allowed on the route; needs the ATES ratings layer
+  pub max_ates_rating: Option<AtesRating>,
+  /// Treat cells of the runout zones layer as impassable; on by default
+  pub avoid_runout_zones: bool,
+  /// Streams of this Strahler order or higher are impassable; needs the stream orders layer
+  pub stream_barrier_order: Option<u32>,
+  /// Extra cost, in metres of level walking, of each step onto a stream cell; needs the stream orders layer
+  pub stream_crossing_cost: f64,
+  /// Upslope area in m² at which a drainage counts as a stream when deriving the stream orders layer
+  pub stream_threshold_area: f64,
+  /// Extra cost, in metres of level walking, of each step onto a cell per hectare of avalanche start zone that
+  /// can reach it; needs the overhead hazard
+  pub overhead_exposure_cost: f64,
+  /// Report the start zones above each point of the path alongside it; needs the overhead hazard
+  pub report_overhead_exposure: bool,
+  /// Base number of visited nodes between exploration callbacks
+  pub exploration_batch_size: usize,
+}
+
+impl Default for PathOptions {
+  fn default() -> Self {
+    Self {
+      max_gradient: 1.0,
+      excluded_aspects: vec![],
+      aspect_gradient_threshold: 0.0,
+      max_ates_rating: None,
+      avoid_runout_zones: true,
+      stream_barrier_order: None,
+      stream_crossing_cost: 0.0,
+      stream_threshold_area: DEFAULT_STREAM_THRESHOLD_AREA,
+      overhead_exposure_cost: 0.0,
+      report_overhead_exposure: false,
+      // Large batch_size (10000) for fast animation - JS throttles to 30fps anyway
+      exploration_batch_size: 10000,
+    }
+  }
+}
+
+impl PathOptions {
+  /// True if any option needs the stream orders layer
+  pub fn uses_streams(&self) -> bool {
+    self.stream_barrier_order.is_some() || self.stream_crossing_cost > 0.0
+  }
+
+  /// True if any option needs the overhead hazard
+  pub fn uses_overhead_exposure(&self) -> bool {
+    self.overhead_exposure_cost > 0.0 || self.report_overhead_exposure
   }
 }
 
-fn distance(a: (usize, usize), b: (usize, usize)) -> f64 {
-  let dx: f64 = (b.0 as isize - a.0 as isize).abs() as f64 * 10.0;
-  let dy: f64 = (b.1 as isize - a.1 as isize).abs() as f64 * 10.0;
+/// Layers `find_path` routes across besides the elevations, all on the elevations' grid
+#[derive(Clone, Copy)]
+pub struct RouteLayers<'a> {
+  pub azimuths: &'a Raster<f64>,
+  pub gradients: &'a Raster<f64>,
+  /// Impassable where positive if `PathOptions::avoid_runout_zones`
+  pub runout_zones: Option<&'a Raster<f64>>,
+  /// Needed by `PathOptions::max_ates_rating`
+  pub ates_ratings: Option<&'a Raster<f64>>,
+  /// Needed by the stream options
+  pub stream_orders: Option<&'a Raster<f64>>,
+  /// Needed by `PathOptions::overhead_exposure_cost`
+  pub overhead: Option<&'a OverheadHazard>,
+}
+
+impl<'a> RouteLayers<'a> {
+  /// Azimuths and gradients without any of the optional layers
+  pub fn new(azimuths: &'a Raster<f64>, gradients: &'a Raster<f64>) -> Self {
+    Self { azimuths, gradients, runout_zones: None, ates_ratings: None, stream_orders: None, overhead: None }
+  }
+}
+
+/// Receives the current search frontier as model coordinates
+pub type ExplorationCallback<'a> = &'a mut dyn FnMut(&[Coordinate]);
+
+/// A pixel along a routed path
+#[derive(Debug, Clone)]
+pub struct PathPoint {
+  pub coordinate: Coordinate,
+  pub elevation: f64,
+  pub azimuth: f64,
+  pub aspect: Aspect,
+}
+
+/// Ground distance in metres between two pixels, given the `[x, y]` pixel spacing in metres
+fn distance(a: (usize, usize), b: (usize, usize), spacing: [f64; 2]) -> f64 {
+  let dx: f64 = a.0.abs_diff(b.0) as f64 * spacing[0];
+  let dy: f64 = a.1.abs_diff(b.1) as f64 * spacing[1];
   ((dx * dx) + (dy * dy)).sqrt()
 }
 
@@ -54,25 +143,20 @@ fn cost_fn(distance: f64, gradient: f64) -> i32 {
 
 /// Exploration tracker using interior mutability for callback batching
 /// Tracks the true expanding frontier (boundary of explored region)
-struct ExplorationTracker {
-  callback: Option<Function>,
+struct ExplorationTracker<'a> {
+  callback: Option<ExplorationCallback<'a>>,
   explored: HashSet<(usize, usize)>,  // All visited nodes
   frontier: HashSet<(usize, usize)>,   // Current boundary nodes (explored with unexplored neighbors)
   batch_counter: usize,
   total_explored: usize,  // Running count for adaptive batch sizing
   base_batch_size: usize,
-  pixel_scale: (f64, f64),  // (dx, dy) for pixel to coord conversion
-  origin: (f64, f64),       // (x, y) origin
+  transform: GeoTransform,  // Pixel to coord conversion, shared with the path output
   width: usize,
   height: usize,
 }
 
-impl ExplorationTracker {
-  fn new(callback: Option<Function>, geotiff: &GeoTiffReader<Cursor<Vec<u8>>>, batch_size: usize, width: usize, height: usize) -> Self {
-    // Get transform parameters from geotiff
-    let origin = geotiff.origin().unwrap_or([0.0, 0.0]);
-    let pixel_scale_arr = geotiff.pixel_size().unwrap_or([1.0/10800.0, -1.0/10800.0]);
-    
+impl<'a> ExplorationTracker<'a> {
+  fn new<T: Copy>(callback: Option<ExplorationCallback<'a>>, raster: &Raster<T>, batch_size: usize) -> Self {
     Self {
       callback,
       explored: HashSet::new(),
@@ -80,10 +164,9 @@ impl ExplorationTracker {
       batch_counter: 0,
       total_explored: 0,
       base_batch_size: batch_size,
-      pixel_scale: (pixel_scale_arr[0], pixel_scale_arr[1]),
-      origin: (origin[0], origin[1]),
-      width,
-      height,
+      transform: raster.center_transform(),
+      width: raster.width(),
+      height: raster.height(),
     }
   }
 
@@ -102,7 +185,7 @@ impl ExplorationTracker {
       // log10(total) gives us roughly: 500->2.7, 5k->3.7, 50k->4.7, 500k->5.7
       // Subtract 2.5 and use as power of 2
       let log_val = (self.total_explored as f64).log10() - 2.5;
-      let power = log_val.max(0.0).min(4.0); // Cap at 16x
+      let power = log_val.clamp(0.0, 4.0); // Cap at 16x
       (2.0_f64.powf(power)) as usize
     };
     
@@ -178,101 +261,87 @@ impl ExplorationTracker {
   }
 
   fn flush(&mut self) {
-    if let Some(ref callback) = self.callback {
+    if let Some(callback) = self.callback.as_mut() {
       if !self.frontier.is_empty() {
-        // Convert frontier to JS array of [lon, lat] pairs
-        let arr = js_sys::Array::new();
-        for (x, y) in &self.frontier {
-          // Convert pixel to coordinate
-          let lon = self.origin.0 + (*x as f64) * self.pixel_scale.0;
-          let lat = self.origin.1 + (*y as f64) * self.pixel_scale.1;
-          
-          let point = js_sys::Array::new();
-          point.push(&JsValue::from_f64(lon));
-          point.push(&JsValue::from_f64(lat));
-          arr.push(&point);
-        }
+        // Convert frontier pixels to coordinates
+        let coordinates: Vec<Coordinate> = self.frontier
+          .iter()
+          .map(|(x, y)| self.transform.apply(*x as f64, *y as f64))
+          .collect();
         
-        let _ = callback.call1(&JsValue::NULL, &arr);
+        callback(&coordinates);
       }
     }
   }
 }
 
-#[wasm_bindgen]
-pub fn find_path_rs(
-  elevations_buffer: &[u8],
-  start: String,
-  end: String,
-  max_gradient: Option<f64>,
-  azimuths_buffer: &[u8],
-  excluded_aspects: JsValue,
-  gradients_buffer: &[u8],
-  aspect_gradient_threshold: Option<f64>,
-  exploration_callback: Option<Function>,
-  exploration_batch_size: Option<usize>,
-  runout_zones_buffer: Option<Vec<u8>>,
-) -> Result<String, JsValue> { 
-  let max_gradient: f64 = max_gradient.unwrap_or(1.0);
-  let excluded_aspects: Vec<Aspect> = if excluded_aspects.is_undefined() || excluded_aspects.is_null() {
-    vec![]
-  } else {
-    serde_wasm_bindgen::from_value(excluded_aspects).unwrap_or(vec![])
-  };
-  let aspect_gradient_threshold: f64 = aspect_gradient_threshold.unwrap_or(0.0);
-
-  let elevations_cursor: Cursor<Vec<u8>> = Cursor::new(elevations_buffer.to_vec());
-  let mut elevations_geotiff: GeoTiffReader<Cursor<Vec<u8>>> = GeoTiffReader::open(elevations_cursor)
-    .map_err(|e| JsValue::from_str(&format!("Failed to open elevations GeoTIFF: {:?}", e)))?;
-  let elevations: Vec<Vec<f64>> = get_raster(&mut elevations_geotiff)?;
-
-  let azimuths_cursor: Cursor<Vec<u8>> = Cursor::new(azimuths_buffer.to_vec());
-  let mut azimuths_geotiff: GeoTiffReader<Cursor<Vec<u8>>> = GeoTiffReader::open(azimuths_cursor)
-    .map_err(|e| JsValue::from_str(&format!("Failed to open azimuths GeoTIFF: {:?}", e)))?;
-  let azimuths: Vec<Vec<f64>> = get_raster(&mut azimuths_geotiff)?;
-
-  let gradients_cursor: Cursor<Vec<u8>> = Cursor::new(gradients_buffer.to_vec());
-  let mut gradients_geotiff: GeoTiffReader<Cursor<Vec<u8>>> = GeoTiffReader::open(gradients_cursor)
-    .map_err(|e| JsValue::from_str(&format!("Failed to open gradients GeoTIFF: {:?}", e)))?;
-  let gradients: Vec<Vec<f64>> = get_raster(&mut gradients_geotiff)?;
-
-  // Parse runout zones if provided
-  let runout_zones: Option<Vec<Vec<f64>>> = if let Some(buffer) = runout_zones_buffer {
-    let runout_cursor: Cursor<Vec<u8>> = Cursor::new(buffer);
-    let mut runout_geotiff: GeoTiffReader<Cursor<Vec<u8>>> = GeoTiffReader::open(runout_cursor)
-      .map_err(|e| JsValue::from_str(&format!("Failed to open runout zones GeoTIFF: {:?}", e)))?;
-    Some(get_raster(&mut runout_geotiff)?)
-  } else {
-    None
-  };
-
-  let start_coord: Coordinate = parse_point_to_coordinate(&start)?;
-  let end_coord: Coordinate = parse_point_to_coordinate(&end)?;
+/// Find the lowest-cost path from `start` to `end` across the elevation raster, avoiding runout zones, excluded
+/// aspects, cells rated above `max_ates_rating` and streams of `stream_barrier_order` or higher, and charging
+/// `stream_crossing_cost` for each step onto a lesser stream and `overhead_exposure_cost` for each step below start
+/// zones of the `overhead` hazard. Nodata cells are impassable. `on_explore` periodically receives the current search frontier.
+pub fn find_path(
+  elevations: &Raster<f64>,
+  layers: RouteLayers,
+  start: Coordinate,
+  end: Coordinate,
+  options: &PathOptions,
+  on_explore: Option<ExplorationCallback>,
+) -> Result<Vec<PathPoint>, PathfinderError> {
+  let max_gradient: f64 = options.max_gradient;
+  let excluded_aspects: &[Aspect] = &options.excluded_aspects;
+  let aspect_gradient_threshold: f64 = options.aspect_gradient_threshold;
+  let RouteLayers { azimuths, gradients, runout_zones, ates_ratings, stream_orders, overhead } = layers;
+  let runout_zones: Option<&Raster<f64>> = runout_zones.filter(|_| options.avoid_runout_zones);
+
+  for (name, layer) in [("azimuths", azimuths), ("gradients", gradients)]
+    .into_iter()
+    .chain(runout_zones.map(|runout| ("runout zones", runout)))
+    .chain(ates_ratings.map(|ratings| ("ATES ratings", ratings)))
+    .chain(stream_orders.map(|orders| ("stream orders", orders)))
+  {
+    if !elevations.same_grid(layer) {
+      return Err(PathfinderError::GridMismatch { layer: name });
+    }
+  }
+  if overhead.is_some_and(|hazard| !elevations.same_grid(hazard.exposure())) {
+    return Err(PathfinderError::GridMismatch { layer: "overhead hazard" });
+  }
 
-  let (start_x, start_y) = elevations_geotiff.coord_to_pixel(start_coord)
-    .ok_or_else(|| JsValue::from_str("Failed to convert start coord to pixel"))?;
-  let start_node: (usize, usize) = (start_x as usize, start_y as usize);
-  let (end_x, end_y) = elevations_geotiff.coord_to_pixel(end_coord)
-    .ok_or_else(|| JsValue::from_str("Failed to convert end coord to pixel"))?;
-  let end_node: (usize, usize) = (end_x as usize, end_y as usize);
+  let start_node: (usize, usize) = elevations.coord_to_pixel(start)
+    .ok_or(PathfinderError::StartOutOfBounds { coordinate: start })?;
+  let end_node: (usize, usize) = elevations.coord_to_pixel(end)
+    .ok_or(PathfinderError::EndOutOfBounds { coordinate: end })?;
+  if elevations.is_nodata(start_node.0, start_node.1) {
+    return Err(PathfinderError::NoDataAtEndpoint { coordinate: start });
+  }
+  if elevations.is_nodata(end_node.0, end_node.1) {
+    return Err(PathfinderError::NoDataAtEndpoint { coordinate: end });
+  }
 
-  let (width, height) = elevations_geotiff.image_info().dimensions
-    .ok_or_else(|| JsValue::from_str("Failed to get image dimensions"))?;
-  let width: usize = width as usize;
-  let height: usize = height as usize;
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
 
   // Create exploration tracker with callback using Rc<RefCell> for interior mutability
-  // Large batch_size (10000) for fast animation - JS throttles to 30fps anyway
-  let batch_size = exploration_batch_size.unwrap_or(10000);
-  let tracker = Rc::new(RefCell::new(ExplorationTracker::new(exploration_callback, &elevations_geotiff, batch_size, width, height)));
+  let tracker = Rc::new(RefCell::new(ExplorationTracker::new(on_explore, elevations, options.exploration_batch_size)));
   let tracker_clone = tracker.clone();
 
+  // Ground spacing varies by row on geographic rasters
+  let row_spacing: Vec<[f64; 2]> = (0..height).map(|y| elevations.pixel_spacing(y)).collect();
+  let step_spacing = |a: usize, b: usize| -> [f64; 2] {
+    let ([ax, ay], [bx, by]) = (row_spacing[a], row_spacing[b]);
+    [(ax + bx) / 2.0, (ay + by) / 2.0]
+  };
+  // The heuristic uses the smallest spacing anywhere so it never overestimates
+  let min_spacing: [f64; 2] = row_spacing
+    .iter()
+    .fold([f64::INFINITY; 2], |[mx, my], &[x, y]| [mx.min(x), my.min(y)]);
+
   let heuristic = |&(x, y): &(usize, usize)| -> i32 {
-    distance((x, y), end_node) as i32
+    distance((x, y), end_node, min_spacing) as i32
   };
 
-  let d: f64 = distance((start_node.0, start_node.1), (end_node.0, end_node.1));
-  let dz: f64 = elevations[end_node.1][end_node.0] - elevations[start_node.1][start_node.0];
+  let d: f64 = distance(start_node, end_node, step_spacing(start_node.1, end_node.1));
+  let dz: f64 = elevations[end_node] - elevations[start_node];
   let gradient: f64 = dz / d;
   
   console_log(&format!(
@@ -295,17 +364,48 @@ pub fn find_path_rs(
       let ny: usize = ((y as isize) + dy) as usize;
 
       if nx < width && ny < height {
+        // Voids in the DEM are impassable
+        if elevations.is_nodata(nx, ny) {
+          continue 'neighbors;
+        }
+
         // Check if neighbor is in a runout zone
-        if let Some(ref runout) = runout_zones {
-          if runout[ny][nx] > 0.0 {
+        if let Some(runout) = runout_zones {
+          if runout[(nx, ny)] > 0.0 {
+            continue 'neighbors;
+          }
+        }
+
+        // Check if neighbor is rated more severe than allowed
+        if let (Some(ratings), Some(max_rating)) = (ates_ratings, options.max_ates_rating) {
+          if AtesRating::from_code(ratings[(nx, ny)]).is_none_or(|rating| rating > max_rating) {
             continue 'neighbors;
           }
         }
 
-        let azimuth: f64 = azimuths[ny][nx];
-        let aspect_gradient: f64 = gradients[ny][nx];
+        // Check if neighbor is on a stream too large to cross, and charge for crossing smaller ones
+        let mut crossing_cost: i32 = 0;
+        if let Some(orders) = stream_orders {
+          let order: f64 = orders[(nx, ny)];
+          if order > 0.0 && !orders.is_nodata_value(order) {
+            if options.stream_barrier_order.is_some_and(|barrier| order >= barrier as f64) {
+              continue 'neighbors;
+            }
+            crossing_cost = options.stream_crossing_cost as i32;
+          }
+        }
+
+        // Charge for the start zones that could release onto the neighbor
+        let mut exposure_cost: i32 = 0;
+        if let Some(hazard) = overhead {
+          let start_zone_area: f64 = hazard.exposure_at(nx, ny).start_zone_area;
+          exposure_cost = (options.overhead_exposure_cost * start_zone_area / 10_000.0) as i32;
+        }
+
+        let azimuth: f64 = azimuths[(nx, ny)];
+        let aspect_gradient: f64 = gradients[(nx, ny)];
         if aspect_gradient > aspect_gradient_threshold {
-          for aspect in &excluded_aspects {
+          for aspect in excluded_aspects {
             // Use 22.5° tolerance to also exclude half of adjacent aspects
             if aspect.contains_azimuth(azimuth, Some(22.5)) {
               continue 'neighbors;
@@ -313,11 +413,11 @@ pub fn find_path_rs(
           }
         }
 
-        let d: f64 = distance((x, y), (nx, ny));
-        let dz: f64 = elevations[ny][nx] - elevations[y][x];
+        let d: f64 = distance((x, y), (nx, ny), step_spacing(y, ny));
+        let dz: f64 = elevations[(nx, ny)] - elevations[(x, y)];
         let gradient: f64 = dz / d;
         if gradient < max_gradient {
-          let cost: i32 = cost_fn(d, gradient);
+          let cost: i32 = cost_fn(d, gradient) + crossing_cost + exposure_cost;
           neighbors.push(((nx, ny), cost));
         }
       }
@@ -335,30 +435,54 @@ pub fn find_path_rs(
 
   let path_nodes: Vec<(usize, usize)> = match result {
     Some((path, _)) => path,
-    None => return Err(JsValue::from_str("No path found")),
+    None => return Err(PathfinderError::UnreachableGoal),
   };
 
-  // Create feature collection with points
-  let results: String = FeatureCollection {
-    features: path_nodes
+  Ok(
+    path_nodes
       .iter()
-      .map(|(x, y)| {
-        let coordinate: Coordinate = elevations_geotiff.pixel_to_coord(*x as u32, *y as u32).unwrap();
-        let elevation: f64 = elevations[*y][*x];
-        let azimuth: f64 = azimuths[*y][*x];
-        let aspect: Aspect = Aspect::from_azimuth(azimuth);
+      .map(|&(x, y)| {
+        let azimuth: f64 = azimuths[(x, y)];
+        PathPoint {
+          coordinate: elevations.pixel_to_coord(x, y),
+          elevation: elevations[(x, y)],
+          azimuth,
+          aspect: Aspect::from_azimuth(azimuth),
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Serialize a path as a GeoJSON FeatureCollection of [lon, lat, elevation] points
+pub fn path_to_geojson(path: &[PathPoint]) -> String {
+  path_to_geojson_with_exposure(path, &[])
+}
+
+/// `path_to_geojson`, adding each point's entry of `exposures` as an `overhead` property of
+/// `{ start_zone_area, max_gradient, relief }`
+pub fn path_to_geojson_with_exposure(path: &[PathPoint], exposures: &[OverheadExposure]) -> String {
+  FeatureCollection {
+    features: path
+      .iter()
+      .enumerate()
+      .map(|(index, point)| {
+        let mut properties = serde_json::json!({
+          "aspect": serde_json::to_value(&point.aspect).unwrap(),
+          "azimuth": point.azimuth.to_string(),
+        }).as_object().unwrap().clone();
+        if let Some(exposure) = exposures.get(index) {
+          properties.insert("overhead".to_string(), serde_json::to_value(exposure).unwrap());
+        }
         geojson::Feature {
           bbox: None,
           geometry: Some(Geometry::new(Value::Point(vec![
-            coordinate.x,
-            coordinate.y,
-            elevation,
+            point.coordinate.x,
+            point.coordinate.y,
+            point.elevation,
           ]))),
           id: None,
-          properties: Some(serde_json::json!({
-            "aspect": serde_json::to_value(&aspect).unwrap(),
-            "azimuth": azimuth.to_string(),
-          }).as_object().unwrap().clone()),
+          properties: Some(properties),
           foreign_members: None,
         }
       })
@@ -366,7 +490,111 @@ pub fn find_path_rs(
     bbox: None,
     foreign_members: None,
   }
-  .to_string();
+  .to_string()
+}
+
+/// Serialize a path as a GPX 1.1 track with elevations
+pub fn path_to_gpx(path: &[PathPoint]) -> String {
+  let mut gpx = String::from(concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<gpx version=\"1.1\" creator=\"pathfinder\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    "  <trk>\n",
+    "    <trkseg>\n",
+  ));
+  for point in path {
+    gpx.push_str(&format!(
+      "      <trkpt lat=\"{}\" lon=\"{}\"><ele>{:.2}</ele></trkpt>\n",
+      point.coordinate.y, point.coordinate.x, point.elevation
+    ));
+  }
+  gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+  gpx
+}
 
-  Ok(results)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  /// 9x9 level plane of 30 m pixels
+  fn plane() -> Raster<f64> {
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    Raster::new(vec![1000.0; 81], 9, 9, transform, CrsInfo::default())
+  }
+
+  fn pixels(elevations: &Raster<f64>, path: &[PathPoint]) -> Vec<(usize, usize)> {
+    path.iter().map(|point| elevations.coord_to_pixel(point.coordinate).unwrap()).collect()
+  }
+
+  #[test]
+  fn closed_barriers_and_outside_endpoints_are_errors() {
+    let elevations: Raster<f64> = plane();
+    let flat: Raster<f64> = elevations.like(0.0);
+    let runout_zones: Raster<f64> = elevations.with_data((0..81).map(|i| (i % 9 == 4) as u8 as f64).collect());
+    let layers = RouteLayers { runout_zones: Some(&runout_zones), ..RouteLayers::new(&flat, &flat) };
+    let (start, end) = (elevations.pixel_to_coord(1, 1), elevations.pixel_to_coord(7, 1));
+    let options = PathOptions::default();
+    assert_eq!(
+      find_path(&elevations, layers, start, end, &options, None).unwrap_err(),
+      PathfinderError::UnreachableGoal
+    );
+
+    let outside = Coordinate { x: 0.0, y: 0.0 };
+    assert_eq!(
+      find_path(&elevations, layers, start, outside, &options, None).unwrap_err(),
+      PathfinderError::EndOutOfBounds { coordinate: outside }
+    );
+  }
+
+  #[test]
+  fn voids_are_impassable_and_cannot_be_endpoints() {
+    let mut elevations: Raster<f64> = plane();
+    elevations.set_nodata(Some(-9999.0));
+    // A void across column 4, open only in the bottom row
+    for y in 0..8 {
+      elevations[(4, y)] = -9999.0;
+    }
+    let flat: Raster<f64> = elevations.like(0.0);
+    let (start, end) = (elevations.pixel_to_coord(1, 1), elevations.pixel_to_coord(7, 1));
+    let options = PathOptions::default();
+    let path: Vec<PathPoint> =
+      find_path(&elevations, RouteLayers::new(&flat, &flat), start, end, &options, None).unwrap();
+    assert!(path.iter().all(|point| point.elevation == 1000.0));
+
+    let void: Coordinate = elevations.pixel_to_coord(4, 1);
+    assert_eq!(
+      find_path(&elevations, RouteLayers::new(&flat, &flat), start, void, &options, None).unwrap_err(),
+      PathfinderError::NoDataAtEndpoint { coordinate: void }
+    );
+  }
+
+  #[test]
+  fn level_route_runs_straight_between_the_endpoints() {
+    let elevations: Raster<f64> = plane();
+    let flat: Raster<f64> = elevations.like(0.0);
+    let (start, end) = (elevations.pixel_to_coord(1, 4), elevations.pixel_to_coord(7, 4));
+    let path: Vec<PathPoint> =
+      find_path(&elevations, RouteLayers::new(&flat, &flat), start, end, &PathOptions::default(), None).unwrap();
+    assert_eq!(pixels(&elevations, &path), (1..8).map(|x| (x, 4)).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn route_goes_around_runout_zones_unless_told_not_to() {
+    let elevations: Raster<f64> = plane();
+    let flat: Raster<f64> = elevations.like(0.0);
+    // Runout across column 4, open only in the bottom row
+    let runout_zones: Raster<f64> =
+      elevations.with_data((0..81).map(|i| (i % 9 == 4 && i / 9 < 8) as u8 as f64).collect());
+    let layers = RouteLayers { runout_zones: Some(&runout_zones), ..RouteLayers::new(&flat, &flat) };
+    let (start, end) = (elevations.pixel_to_coord(1, 1), elevations.pixel_to_coord(7, 1));
+
+    let path: Vec<(usize, usize)> =
+      pixels(&elevations, &find_path(&elevations, layers, start, end, &PathOptions::default(), None).unwrap());
+    assert!(path.contains(&(4, 8)));
+    assert!(path.iter().all(|&(x, y)| runout_zones[(x, y)] == 0.0));
+
+    let options = PathOptions { avoid_runout_zones: false, ..Default::default() };
+    let path: Vec<PathPoint> = find_path(&elevations, layers, start, end, &options, None).unwrap();
+    assert_eq!(path.len(), 7);
+  }
+}
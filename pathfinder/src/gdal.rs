@@ -0,0 +1,167 @@
+//! GDAL-specific TIFF tags that georaster doesn't expose.
+// GDAL TIFF driver: https://gdal.org/drivers/raster/gtiff.html#metadata
+
+use tiff::tags::Tag;
+
+/// GDAL_METADATA: XML blob of dataset and per-band metadata items
+pub(crate) const GDAL_METADATA: Tag = Tag::Unknown(42112);
+
+/// GDAL_NODATA: ASCII encoding of the value marking missing cells
+pub(crate) const GDAL_NODATA: Tag = Tag::GdalNodata;
+
+/// Parse a GDAL_NODATA string such as "-9999", "-3.4028234663852886e+38" or "nan"
+pub(crate) fn parse_nodata(value: &str) -> Option<f64> {
+  value.trim_matches(|c: char| c.is_whitespace() || c == '\0').parse().ok()
+}
+
+/// Format a nodata value the way GDAL writes it
+pub(crate) fn format_nodata(value: f64) -> String {
+  if value.is_nan() {
+    "nan".to_string()
+  } else {
+    value.to_string()
+  }
+}
+
+/// A single `<Item>` of a GDAL_METADATA document
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MetadataItem {
+  pub name: String,
+  /// Zero-based band index, or `None` for dataset-level items
+  pub sample: Option<usize>,
+  pub role: Option<String>,
+  pub value: String,
+}
+
+/// Parsed GDAL_METADATA tag
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct GdalMetadata {
+  pub items: Vec<MetadataItem>,
+}
+
+impl GdalMetadata {
+  /// Parse the `<GDALMetadata><Item name=".." sample="..">value</Item>...</GDALMetadata>` document.
+  /// Malformed items are skipped.
+  pub fn parse(xml: &str) -> Self {
+    let mut items: Vec<MetadataItem> = Vec::new();
+    let mut rest: &str = xml;
+    while let Some(start) = rest.find("<Item") {
+      rest = &rest[start + "<Item".len()..];
+      let Some(tag_end) = rest.find('>') else { break };
+      let attributes: &str = &rest[..tag_end];
+      rest = &rest[tag_end + 1..];
+      if attributes.ends_with('/') {
+        continue;
+      }
+      let Some(value_end) = rest.find("</Item>") else { break };
+      let value: &str = &rest[..value_end];
+      rest = &rest[value_end..];
+
+      if let Some(name) = attribute(attributes, "name") {
+        items.push(MetadataItem {
+          name,
+          sample: attribute(attributes, "sample").and_then(|s| s.parse().ok()),
+          role: attribute(attributes, "role"),
+          value: unescape(value.trim()),
+        });
+      }
+    }
+    Self { items }
+  }
+
+  /// Value of the item `name` for band `sample`
+  pub fn band_item(&self, name: &str, sample: usize) -> Option<&str> {
+    self.items
+      .iter()
+      .find(|item| item.sample == Some(sample) && item.name.eq_ignore_ascii_case(name))
+      .map(|item| item.value.as_str())
+  }
+
+  /// Description of band `sample`, which GDAL stores as a `DESCRIPTION` item with the `description` role
+  pub fn band_description(&self, sample: usize) -> Option<&str> {
+    self.items
+      .iter()
+      .find(|item| item.sample == Some(sample) && item.role.as_deref() == Some("description"))
+      .map(|item| item.value.as_str())
+  }
+
+  /// Metadata naming each band in order
+  pub fn with_band_descriptions<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+    let items: Vec<MetadataItem> = names
+      .into_iter()
+      .enumerate()
+      .map(|(sample, name)| MetadataItem {
+        name: "DESCRIPTION".to_string(),
+        sample: Some(sample),
+        role: Some("description".to_string()),
+        value: name.to_string(),
+      })
+      .collect();
+    Self { items }
+  }
+
+  /// Serialize as a GDAL_METADATA document
+  pub fn to_xml(&self) -> String {
+    let mut xml = String::from("<GDALMetadata>\n");
+    for item in &self.items {
+      xml.push_str(&format!("  <Item name=\"{}\"", escape(&item.name)));
+      if let Some(sample) = item.sample {
+        xml.push_str(&format!(" sample=\"{}\"", sample));
+      }
+      if let Some(role) = &item.role {
+        xml.push_str(&format!(" role=\"{}\"", escape(role)));
+      }
+      xml.push_str(&format!(">{}</Item>\n", escape(&item.value)));
+    }
+    xml.push_str("</GDALMetadata>\n");
+    xml
+  }
+
+  /// Numeric scale and offset to apply to raw samples of band `sample`, as `value * scale + offset`
+  pub fn scale_offset(&self, sample: usize) -> (f64, f64) {
+    let number = |name: &str, default: f64| -> f64 {
+      self.band_item(name, sample).and_then(|v| v.parse().ok()).unwrap_or(default)
+    };
+    (number("SCALE", 1.0), number("OFFSET", 0.0))
+  }
+}
+
+/// Value of `name="..."` within an XML start tag's attribute list
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+  let mut rest: &str = attributes;
+  while let Some(pos) = rest.find(name) {
+    let preceded_by_space = rest[..pos].ends_with(char::is_whitespace) || pos == 0;
+    let after: &str = rest[pos + name.len()..].trim_start();
+    if preceded_by_space {
+      if let Some(after) = after.strip_prefix('=') {
+        let after: &str = after.trim_start();
+        let quote: char = after.chars().next()?;
+        if quote == '"' || quote == '\'' {
+          let value: &str = &after[1..];
+          let end: usize = value.find(quote)?;
+          return Some(unescape(&value[..end]));
+        }
+      }
+    }
+    rest = &rest[pos + name.len()..];
+  }
+  None
+}
+
+fn escape(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+fn unescape(value: &str) -> String {
+  value
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&amp;", "&")
+}
@@ -1,54 +1,440 @@
-use std::io::Cursor;
-use tiff::encoder::colortype::Gray32Float;
-use tiff::encoder::TiffEncoder;
-use tiff::tags::Tag;
-use wasm_bindgen::prelude::*;
-
-pub fn serialize_to_geotiff(
-  raster: Vec<Vec<f64>>,
-  geo_keys: &Vec<u32>,
-  origin: &[f64; 2]
-) -> Result<Vec<u8>, JsValue> {
-  let height: usize = raster.len();
-  let width: usize = raster[0].len();
+use std::io::{Cursor, Seek, Write};
+use tiff::encoder::compression::{CompressionAlgorithm, Compressor, Deflate, Lzw, Uncompressed};
+use tiff::encoder::{DirectoryEncoder, TiffEncoder, TiffKindStandard};
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, PlanarConfiguration, Predictor, SampleFormat, Tag};
+use tiff::TiffError;
+
+use crate::{
+  error::PathfinderError,
+  gdal::{format_nodata, GdalMetadata, GDAL_METADATA, GDAL_NODATA},
+  raster::{CrsInfo, GeoTransform, Raster},
+};
+
+/// Uncompressed strips are sized to about this many bytes, as libtiff does
+const STRIP_BYTES: usize = 8192;
+
+/// Overviews of a strip-based image are generated until it fits in a block of this size
+const DEFAULT_OVERVIEW_BLOCK: usize = 256;
+
+/// Codec applied to each strip or tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeoTiffCompression {
+  #[default]
+  None,
+  Lzw,
+  Deflate,
+}
+
+/// Compression and internal layout of the GeoTIFFs written by `serialize_to_geotiff_with_options`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GeoTiffOptions {
+  pub compression: GeoTiffCompression,
+  /// Apply the floating-point predictor (Predictor=3) before compressing; ignored when uncompressed
+  pub predictor: bool,
+  /// Side of the square internal tiles, a multiple of 16; the image is written in strips if `None`
+  pub tile_size: Option<u32>,
+  /// Append nearest-neighbour overviews, halving the resolution until the image fits in a single tile
+  pub overviews: bool,
+}
+
+impl GeoTiffOptions {
+  /// Deflate with the floating-point predictor, in 256x256 tiles
+  pub fn compressed() -> Self {
+    Self {
+      compression: GeoTiffCompression::Deflate,
+      predictor: true,
+      tile_size: Some(256),
+      overviews: false,
+    }
+  }
+
+  /// `compressed` plus overviews, following the Cloud-Optimized GeoTIFF profile.
+  /// Each IFD is written after its image data rather than all IFDs leading the file.
+  pub fn cloud_optimized() -> Self {
+    Self { overviews: true, ..Self::compressed() }
+  }
+}
+
+/// One resolution level of the image being written, with one sample buffer per band
+struct Level {
+  width: usize,
+  height: usize,
+  bands: Vec<Vec<f32>>,
+}
+
+impl Level {
+  /// Next overview level, taking the top-left sample of each 2x2 block
+  fn downsample(&self) -> Level {
+    let width: usize = self.width.div_ceil(2);
+    let height: usize = self.height.div_ceil(2);
+    let bands: Vec<Vec<f32>> = self
+      .bands
+      .iter()
+      .map(|samples| {
+        let mut downsampled: Vec<f32> = Vec::with_capacity(width * height);
+        for y in 0..height {
+          for x in 0..width {
+            downsampled.push(samples[2 * y * self.width + 2 * x]);
+          }
+        }
+        downsampled
+      })
+      .collect();
+    Level { width, height, bands }
+  }
+}
+
+fn write_error(context: &'static str) -> impl Fn(TiffError) -> PathfinderError {
+  move |e| PathfinderError::GeoTiffWrite { message: format!("{}: {}", context, e) }
+}
+
+/// Write `raster` as an uncompressed, strip-based Float32 GeoTIFF with the same georeferencing
+/// (transform, GeoKeys, CRS params) as its source
+pub fn serialize_to_geotiff(raster: &Raster<f64>) -> Result<Vec<u8>, PathfinderError> {
+  serialize_to_geotiff_with_options(raster, &GeoTiffOptions::default())
+}
+
+/// Write `raster` as a Float32 GeoTIFF with the same georeferencing as its source, compressed and laid out per `options`
+pub fn serialize_to_geotiff_with_options(
+  raster: &Raster<f64>,
+  options: &GeoTiffOptions,
+) -> Result<Vec<u8>, PathfinderError> {
+  let level = Level {
+    width: raster.width(),
+    height: raster.height(),
+    bands: vec![raster.data().iter().map(|&x| x as f32).collect()],
+  };
+  write_geotiff(raster, raster.nodata(), None, level, options)
+}
+
+/// Write same-grid rasters as the named bands of one Float32 GeoTIFF, georeferenced like the first band.
+/// Band names are stored as GDAL band descriptions. GeoTIFFs have a single nodata value, so the nodata cells
+/// of every band are rewritten to the first nodata value among the bands.
+pub fn serialize_bands_to_geotiff(
+  bands: &[(&str, &Raster<f64>)],
+  options: &GeoTiffOptions,
+) -> Result<Vec<u8>, PathfinderError> {
+  let Some(&(_, first)) = bands.first() else {
+    return Err(PathfinderError::GeoTiffWrite { message: "No bands to write".to_string() });
+  };
+  if let Some((name, _)) = bands.iter().find(|(_, band)| !first.same_grid(band)) {
+    return Err(PathfinderError::GeoTiffWrite {
+      message: format!("The {} band does not match the grid of the first band", name),
+    });
+  }
+
+  let nodata: Option<f64> = bands.iter().find_map(|(_, band)| band.nodata());
+  let level = Level {
+    width: first.width(),
+    height: first.height(),
+    bands: bands
+      .iter()
+      .map(|(_, band)| {
+        band
+          .data()
+          .iter()
+          .map(|&x| if band.is_nodata_value(x) { nodata.unwrap_or(x) as f32 } else { x as f32 })
+          .collect()
+      })
+      .collect(),
+  };
+  let metadata: GdalMetadata = GdalMetadata::with_band_descriptions(bands.iter().map(|&(name, _)| name));
+  write_geotiff(first, nodata, Some(metadata), level, options)
+}
+
+/// Write `level` and its overviews, georeferenced like `georeference`
+fn write_geotiff(
+  georeference: &Raster<f64>,
+  nodata: Option<f64>,
+  metadata: Option<GdalMetadata>,
+  mut level: Level,
+  options: &GeoTiffOptions,
+) -> Result<Vec<u8>, PathfinderError> {
+  if let Some(tile_size) = options.tile_size {
+    if tile_size == 0 || tile_size % 16 != 0 {
+      return Err(PathfinderError::GeoTiffWrite {
+        message: format!("Tile size must be a positive multiple of 16, got {}", tile_size),
+      });
+    }
+  }
+
   let buffer: Vec<u8> = Vec::new();
   let mut cursor: Cursor<Vec<u8>> = Cursor::new(buffer);
-  let mut encoder: TiffEncoder<&mut Cursor<Vec<u8>>> = TiffEncoder::new(&mut cursor)
-    .map_err(|e| JsValue::from_str(&format!("Failed to create encoder: {}", e)))?;
+  let mut encoder: TiffEncoder<&mut Cursor<Vec<u8>>> =
+    TiffEncoder::new(&mut cursor).map_err(write_error("Failed to create encoder"))?;
+
+  // Padding in edge tiles is nodata, so readers that ignore the image size still see it as empty
+  let fill: f32 = nodata.unwrap_or(0.0) as f32;
   {
-    let mut image = encoder
-      .new_image::<Gray32Float>(width as u32, height as u32)
-      .map_err(|e| JsValue::from_str(&format!("Failed to create image: {}", e)))?;
-
-    image
-      .encoder()
-      .write_tag(Tag::Unknown(34735), &**geo_keys)
-      .map_err(|e| JsValue::from_str(&format!("Failed to write geo_keys: {}", e)))?;
-    image
-      .encoder()
-      .write_tag(Tag::Unknown(34737), "NAD83|}")
-      .map_err(|e| JsValue::from_str(&format!("Failed to write CRS: {}", e)))?;
-    let geo_doubles: Vec<f64> = vec![6378137.0, 298.257222101];
-    image
-      .encoder()
-      .write_tag(Tag::Unknown(34736), &geo_doubles[..])
-      .map_err(|e| JsValue::from_str(&format!("Failed to write geo_doubles: {}", e)))?;
-    let one_third_arc_second: f64 = 1.0 / 10800.0;
-    let pixel_scale: Vec<f64> = vec![one_third_arc_second, one_third_arc_second, 0.0];
-    image
-      .encoder()
-      .write_tag(Tag::Unknown(33550), &pixel_scale[..])
-      .map_err(|e| JsValue::from_str(&format!("Failed to write pixel scale: {}", e)))?;
-    let tie_points: Vec<f64> = vec![0.0, 0.0, 0.0, origin[0], origin[1], 0.0];
-    image
-      .encoder()
-      .write_tag(Tag::Unknown(33922), &tie_points[..])
-      .map_err(|e| JsValue::from_str(&format!("Failed to write tie points: {}", e)))?;
-
-    let flattened: Vec<f32> = raster.into_iter().flatten().map(|x| x as f32).collect();
-    image
-      .write_data(&flattened)
-      .map_err(|e| JsValue::from_str(&format!("Failed to write data: {}", e)))?;
+    let mut directory = encoder.new_directory().map_err(write_error("Failed to create image"))?;
+    write_georeferencing(&mut directory, georeference)?;
+    if let Some(nodata) = nodata {
+      directory
+        .write_tag(GDAL_NODATA, &format_nodata(nodata)[..])
+        .map_err(write_error("Failed to write nodata"))?;
+    }
+    if let Some(metadata) = metadata {
+      directory
+        .write_tag(GDAL_METADATA, &metadata.to_xml()[..])
+        .map_err(write_error("Failed to write metadata"))?;
+    }
+    write_level(&mut directory, &level, fill, options, false)?;
+    directory.finish().map_err(write_error("Failed to write image directory"))?;
+  }
+
+  if options.overviews {
+    let block: usize = options.tile_size.map_or(DEFAULT_OVERVIEW_BLOCK, |size| size as usize);
+    while level.width > block || level.height > block {
+      level = level.downsample();
+      let mut directory = encoder.new_directory().map_err(write_error("Failed to create overview"))?;
+      write_level(&mut directory, &level, fill, options, true)?;
+      directory.finish().map_err(write_error("Failed to write overview directory"))?;
+    }
   }
   Ok(cursor.into_inner())
-}
\ No newline at end of file
+}
+
+/// Write the GeoKeys, CRS params and transform of `raster`
+fn write_georeferencing<W: Write + Seek>(
+  directory: &mut DirectoryEncoder<W, TiffKindStandard>,
+  raster: &Raster<f64>,
+) -> Result<(), PathfinderError> {
+  let crs: &CrsInfo = raster.crs();
+  if !crs.geo_keys.is_empty() {
+    directory
+      .write_tag(Tag::GeoKeyDirectoryTag, &crs.geo_keys[..])
+      .map_err(write_error("Failed to write geo_keys"))?;
+  }
+  if let Some(double_params) = crs.double_params.as_deref().filter(|params| !params.is_empty()) {
+    directory
+      .write_tag(Tag::GeoDoubleParamsTag, double_params)
+      .map_err(write_error("Failed to write geo_doubles"))?;
+  }
+  if let Some(ascii_params) = crs.ascii_params.as_deref() {
+    directory
+      .write_tag(Tag::GeoAsciiParamsTag, ascii_params)
+      .map_err(write_error("Failed to write CRS"))?;
+  }
+
+  // Rotated grids need the full affine transform; north-up grids use the more widely supported scale + tie point
+  let transform: &GeoTransform = raster.transform();
+  if transform.row_rotation != 0.0 || transform.column_rotation != 0.0 {
+    let model_transformation: [f64; 16] = [
+      transform.pixel_width, transform.row_rotation, 0.0, transform.origin_x,
+      transform.column_rotation, transform.pixel_height, 0.0, transform.origin_y,
+      0.0, 0.0, 0.0, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ];
+    directory
+      .write_tag(Tag::ModelTransformationTag, &model_transformation[..])
+      .map_err(write_error("Failed to write transformation"))?;
+  } else {
+    let pixel_scale: [f64; 3] = [transform.pixel_width, -transform.pixel_height, 0.0];
+    directory
+      .write_tag(Tag::ModelPixelScaleTag, &pixel_scale[..])
+      .map_err(write_error("Failed to write pixel scale"))?;
+    let tie_points: [f64; 6] = [0.0, 0.0, 0.0, transform.origin_x, transform.origin_y, 0.0];
+    directory
+      .write_tag(Tag::ModelTiepointTag, &tie_points[..])
+      .map_err(write_error("Failed to write tie points"))?;
+  }
+  Ok(())
+}
+
+/// Write the strips or tiles of `level` and the image tags describing them.
+/// Multi-band levels are band-interleaved (PlanarConfiguration=2): all chunks of band 1, then band 2, ...
+fn write_level<W: Write + Seek>(
+  directory: &mut DirectoryEncoder<W, TiffKindStandard>,
+  level: &Level,
+  fill: f32,
+  options: &GeoTiffOptions,
+  is_overview: bool,
+) -> Result<(), PathfinderError> {
+  let (width, height) = (level.width, level.height);
+  let predictor: bool = options.predictor && options.compression != GeoTiffCompression::None;
+  let (chunk_width, chunk_height) = match options.tile_size {
+    Some(tile_size) => (tile_size as usize, tile_size as usize),
+    None => (width, (STRIP_BYTES / (width * 4).max(1)).clamp(1, height.max(1))),
+  };
+
+  let mut offsets: Vec<u32> = Vec::new();
+  let mut byte_counts: Vec<u32> = Vec::new();
+  for band in &level.bands {
+    for y0 in (0..height).step_by(chunk_height) {
+      for x0 in (0..width).step_by(chunk_width) {
+        // Tiles are always full size; the last strip stops at the bottom of the image
+        let rows: usize = if options.tile_size.is_some() { chunk_height } else { chunk_height.min(height - y0) };
+        let mut samples: Vec<f32> = Vec::with_capacity(chunk_width * rows);
+        for y in y0..y0 + rows {
+          for x in x0..x0 + chunk_width {
+            samples.push(if x < width && y < height { band[y * width + x] } else { fill });
+          }
+        }
+
+        let bytes: Vec<u8> = encode_chunk(&samples, chunk_width, options.compression, predictor)?;
+        let offset: u64 = directory.write_data(&bytes[..]).map_err(write_error("Failed to write data"))?;
+        let offset: u32 = u32::try_from(offset)
+          .map_err(|_| PathfinderError::GeoTiffWrite { message: "GeoTIFF exceeds 4 GiB".to_string() })?;
+        offsets.push(offset);
+        byte_counts.push(bytes.len() as u32);
+      }
+    }
+  }
+
+  let compression: CompressionMethod = match options.compression {
+    GeoTiffCompression::None => CompressionMethod::None,
+    GeoTiffCompression::Lzw => CompressionMethod::LZW,
+    GeoTiffCompression::Deflate => CompressionMethod::Deflate,
+  };
+  directory
+    .write_tag(Tag::NewSubfileType, is_overview as u32)
+    .map_err(write_error("Failed to write image tags"))?;
+  directory
+    .write_tag(Tag::ImageWidth, width as u32)
+    .map_err(write_error("Failed to write image tags"))?;
+  directory
+    .write_tag(Tag::ImageLength, height as u32)
+    .map_err(write_error("Failed to write image tags"))?;
+  let samples: usize = level.bands.len();
+  let planar_configuration: PlanarConfiguration =
+    if samples > 1 { PlanarConfiguration::Planar } else { PlanarConfiguration::Chunky };
+  let mut short_tags: Vec<(Tag, u16)> = vec![
+    (Tag::Compression, compression.to_u16()),
+    (Tag::PhotometricInterpretation, PhotometricInterpretation::BlackIsZero.to_u16()),
+    (Tag::SamplesPerPixel, samples as u16),
+    (Tag::PlanarConfiguration, planar_configuration.to_u16()),
+  ];
+  if predictor {
+    short_tags.push((Tag::Predictor, Predictor::FloatingPoint.to_u16()));
+  }
+  for (tag, value) in short_tags {
+    directory.write_tag(tag, value).map_err(write_error("Failed to write image tags"))?;
+  }
+  directory
+    .write_tag(Tag::BitsPerSample, &vec![32u16; samples][..])
+    .map_err(write_error("Failed to write image tags"))?;
+  directory
+    .write_tag(Tag::SampleFormat, &vec![SampleFormat::IEEEFP.to_u16(); samples][..])
+    .map_err(write_error("Failed to write image tags"))?;
+  if samples > 1 {
+    // Bands beyond the first gray sample are unspecified extra samples
+    directory
+      .write_tag(Tag::ExtraSamples, &vec![0u16; samples - 1][..])
+      .map_err(write_error("Failed to write image tags"))?;
+  }
+
+  let (offsets_tag, byte_counts_tag) = match options.tile_size {
+    Some(tile_size) => {
+      directory.write_tag(Tag::TileWidth, tile_size).map_err(write_error("Failed to write tile size"))?;
+      directory.write_tag(Tag::TileLength, tile_size).map_err(write_error("Failed to write tile size"))?;
+      (Tag::TileOffsets, Tag::TileByteCounts)
+    }
+    None => {
+      directory
+        .write_tag(Tag::RowsPerStrip, chunk_height as u32)
+        .map_err(write_error("Failed to write strip size"))?;
+      (Tag::StripOffsets, Tag::StripByteCounts)
+    }
+  };
+  directory
+    .write_tag(offsets_tag, &offsets[..])
+    .map_err(write_error("Failed to write chunk offsets"))?;
+  directory
+    .write_tag(byte_counts_tag, &byte_counts[..])
+    .map_err(write_error("Failed to write chunk byte counts"))?;
+  Ok(())
+}
+
+/// Serialize one strip or tile of `chunk_width`-sample rows, applying the predictor and compression
+fn encode_chunk(
+  samples: &[f32],
+  chunk_width: usize,
+  compression: GeoTiffCompression,
+  predictor: bool,
+) -> Result<Vec<u8>, PathfinderError> {
+  let raw: Vec<u8> = if predictor {
+    samples.chunks(chunk_width).flat_map(floating_point_predictor).collect()
+  } else {
+    samples.iter().flat_map(|sample| sample.to_ne_bytes()).collect()
+  };
+
+  let mut compressor: Compressor = match compression {
+    GeoTiffCompression::None => Compressor::Uncompressed(Uncompressed),
+    GeoTiffCompression::Lzw => Compressor::Lzw(Lzw),
+    GeoTiffCompression::Deflate => Compressor::Deflate(Deflate::default()),
+  };
+  let mut bytes: Vec<u8> = Vec::new();
+  compressor
+    .write_to(&mut bytes, &raw)
+    .map_err(|e| PathfinderError::GeoTiffWrite { message: format!("Failed to compress data: {}", e) })?;
+  Ok(bytes)
+}
+
+/// Floating-point predictor for one row: split the samples into big-endian byte planes, then
+/// difference each byte from the one before it
+fn floating_point_predictor(row: &[f32]) -> Vec<u8> {
+  let mut bytes: Vec<u8> = vec![0; row.len() * 4];
+  for (i, sample) in row.iter().enumerate() {
+    for (plane, byte) in sample.to_be_bytes().into_iter().enumerate() {
+      bytes[plane * row.len() + i] = byte;
+    }
+  }
+  for i in (1..bytes.len()).rev() {
+    bytes[i] = bytes[i].wrapping_sub(bytes[i - 1]);
+  }
+  bytes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{read_geotiff, read_geotiff_bands, CrsInfo, GeoTransform};
+  use std::io::Cursor;
+
+  /// 40x30 grid of 30 m pixels with a void, tagged PixelIsArea in UTM zone 11N
+  fn grid() -> Raster<f64> {
+    let crs = CrsInfo { geo_keys: vec![1, 1, 0, 2, 1025, 0, 1, 1, 3072, 0, 1, 32611], ..Default::default() };
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    let data: Vec<f64> = (0..40 * 30).map(|index| 1000.0 + (index % 40) as f64 * 2.5 + (index / 40) as f64).collect();
+    let mut raster = Raster::new(data, 40, 30, transform, crs);
+    raster.set_nodata(Some(-9999.0));
+    raster[(7, 3)] = -9999.0;
+    raster
+  }
+
+  #[test]
+  fn geotiff_round_trip_keeps_samples_and_georeferencing_in_every_layout() {
+    let layouts: [GeoTiffOptions; 4] = [
+      GeoTiffOptions::default(),
+      GeoTiffOptions { compression: GeoTiffCompression::Lzw, predictor: true, ..Default::default() },
+      GeoTiffOptions::compressed(),
+      GeoTiffOptions { tile_size: Some(16), ..GeoTiffOptions::cloud_optimized() },
+    ];
+    for options in layouts {
+      let bytes: Vec<u8> = serialize_to_geotiff_with_options(&grid(), &options).unwrap();
+      let raster: Raster<f64> = read_geotiff(Cursor::new(bytes)).unwrap();
+      assert_eq!(raster.data(), grid().data(), "{options:?}");
+      assert_eq!(raster.transform(), grid().transform(), "{options:?}");
+      assert_eq!(raster.crs().geo_keys, grid().crs().geo_keys, "{options:?}");
+      assert_eq!(raster.nodata(), Some(-9999.0), "{options:?}");
+    }
+  }
+
+  #[test]
+  fn bands_round_trip_by_name() {
+    let elevations: Raster<f64> = grid();
+    let mut slopes: Raster<f64> = elevations.map(|elevation| elevation / 1000.0);
+    slopes.set_nodata(Some(-1.0));
+    slopes[(7, 3)] = -1.0;
+    let bytes: Vec<u8> =
+      serialize_bands_to_geotiff(&[("elevations", &elevations), ("slopes", &slopes)], &GeoTiffOptions::compressed())
+        .unwrap();
+    let bands: Vec<(String, Raster<f64>)> = read_geotiff_bands(Cursor::new(bytes)).unwrap();
+    let names: Vec<&str> = bands.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["elevations", "slopes"]);
+    assert_eq!(bands[0].1.data(), elevations.data());
+    assert_eq!(bands[1].1[(20, 10)], (slopes[(20, 10)] as f32) as f64);
+    // GeoTIFFs hold one nodata value, so the second band's void takes the first band's
+    assert_eq!(bands[1].1[(7, 3)], -9999.0);
+  }
+}
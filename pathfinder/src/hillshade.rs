@@ -0,0 +1,121 @@
+use crate::{
+  azimuth::{surface_gradient, SlopeAlgorithm, DERIVED_NODATA},
+  raster::Raster,
+};
+
+/// Light direction and surface model for `compute_hillshade`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HillshadeOptions {
+  /// Direction the light comes from, in degrees clockwise from north
+  pub azimuth: f64,
+  /// Angle of the light above the horizon, in degrees
+  pub altitude: f64,
+  /// Vertical exaggeration applied to the elevations
+  pub z_factor: f64,
+  /// Blend light from 225°, 270°, 315° and 360°, each weighted by how squarely it strikes the slope's aspect,
+  /// so relief stays legible on slopes facing away from a single light; `azimuth` is ignored
+  pub multidirectional: bool,
+  pub algorithm: SlopeAlgorithm,
+}
+
+impl Default for HillshadeOptions {
+  /// Light from the northwest at 45°, with Horn gradients, matching `gdaldem hillshade`
+  fn default() -> Self {
+    Self {
+      azimuth: 315.0,
+      altitude: 45.0,
+      z_factor: 1.0,
+      multidirectional: false,
+      algorithm: SlopeAlgorithm::Horn,
+    }
+  }
+}
+
+/// Brightness in [0, 1] of a surface with gradients `zx` (east) and `zy` (north) lit from `azimuth`/`altitude`
+fn illumination(zx: f64, zy: f64, azimuth: f64, altitude: f64) -> f64 {
+  let (sin_azimuth, cos_azimuth) = azimuth.to_radians().sin_cos();
+  let (sin_altitude, cos_altitude) = altitude.to_radians().sin_cos();
+  let cos_incidence: f64 = (sin_altitude - (zx * sin_azimuth + zy * cos_azimuth) * cos_altitude)
+    / (1.0 + zx * zx + zy * zy).sqrt();
+  cos_incidence.max(0.0)
+}
+
+/// Shaded relief from 0 (shadow) to 255 (facing the light), on the same grid as `elevations`.
+/// Voids, and pixels without enough neighbours for a gradient, are `DERIVED_NODATA`.
+pub fn compute_hillshade(elevations: &Raster<f64>, options: &HillshadeOptions) -> Raster<f64> {
+  let mut hillshade: Raster<f64> = elevations.like(0.0);
+  if elevations.nodata().is_some() {
+    hillshade.set_nodata(Some(DERIVED_NODATA));
+  }
+
+  for y in 0..elevations.height() {
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(y);
+    for x in 0..elevations.width() {
+      let gradient: Option<(f64, f64)> = if elevations.is_nodata(x, y) {
+        None
+      } else {
+        surface_gradient(elevations, x, y, options.algorithm.kernel())
+      };
+      let Some((gx, gy)) = gradient else {
+        hillshade.set_nodata(Some(DERIVED_NODATA));
+        hillshade[(x, y)] = DERIVED_NODATA;
+        continue;
+      };
+
+      // Rows run south, so flip gy to get the northward gradient
+      let zx: f64 = options.z_factor * gx / spacing_x;
+      let zy: f64 = -options.z_factor * gy / spacing_y;
+
+      let brightness: f64 = if options.multidirectional {
+        // Aspect as the direction the slope faces; sin² weights over azimuths 45° apart always sum to 2
+        let aspect: f64 = (-zx).atan2(-zy);
+        [225.0_f64, 270.0, 315.0, 360.0]
+          .iter()
+          .map(|&azimuth| (aspect - azimuth.to_radians()).sin().powi(2) * illumination(zx, zy, azimuth, options.altitude))
+          .sum::<f64>()
+          / 2.0
+      } else {
+        illumination(zx, zy, options.azimuth, options.altitude)
+      };
+      hillshade[(x, y)] = 255.0 * brightness;
+    }
+  }
+
+  hillshade
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  /// 7x7 plane of 30 m pixels facing `aspect` degrees at `slope` degrees
+  fn plane(aspect: f64, slope: f64) -> Raster<f64> {
+    let (east, north) = aspect.to_radians().sin_cos();
+    let fall: f64 = slope.to_radians().tan();
+    let data: Vec<f64> = (0..49)
+      .map(|index| 1000.0 - fall * 30.0 * (east * (index % 7) as f64 - north * (index / 7) as f64))
+      .collect();
+    Raster::new(data, 7, 7, GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]), CrsInfo::default())
+  }
+
+  #[test]
+  fn flat_ground_is_lit_by_the_sine_of_the_altitude() {
+    for multidirectional in [false, true] {
+      let options = HillshadeOptions { multidirectional, ..Default::default() };
+      let hillshade: Raster<f64> = compute_hillshade(&plane(0.0, 0.0), &options);
+      let expected: f64 = 255.0 * 45f64.to_radians().sin();
+      assert!(hillshade.data().iter().all(|value| (value - expected).abs() < 1e-9), "{multidirectional}");
+    }
+  }
+
+  #[test]
+  fn slope_square_to_the_light_is_fully_lit_and_the_far_side_is_in_shadow() {
+    let options = HillshadeOptions::default();
+    // Light from 315° at 45° strikes a 45° slope facing north-west head on
+    let facing: Raster<f64> = compute_hillshade(&plane(315.0, 45.0), &options);
+    assert!((facing[(3, 3)] - 255.0).abs() < 1e-9, "{}", facing[(3, 3)]);
+    let away: Raster<f64> = compute_hillshade(&plane(135.0, 50.0), &options);
+    assert_eq!(away[(3, 3)], 0.0);
+  }
+}
@@ -0,0 +1,862 @@
+use geojson::{Feature, FeatureCollection, Geometry, Value};
+use serde::{Deserialize, Serialize};
+use std::{
+  cmp::{Ordering, Reverse},
+  collections::{BinaryHeap, HashMap},
+  str::FromStr,
+};
+
+use georaster::Coordinate;
+
+use crate::{azimuth::DERIVED_NODATA, error::PathfinderError, raster::Raster};
+
+/// Upslope area in m² at which a drainage counts as a stream, about a tenth of a square kilometre
+pub const DEFAULT_STREAM_THRESHOLD_AREA: f64 = 100_000.0;
+
+/// Rise per cell (m) across filled depressions before D8 routing
+pub(crate) const FILL_EPSILON: f64 = 1e-4;
+
+/// Neighbour offsets (dx, dy) clockwise from north, indexed by D8 direction code
+const NEIGHBORS: [(isize, isize); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+/// D-infinity triangular facets as (cardinal, diagonal) direction codes
+const FACETS: [(usize, usize); 8] = [(0, 1), (2, 1), (2, 3), (4, 3), (4, 5), (6, 5), (6, 7), (0, 7)];
+
+/// Freeman (1991) exponent on slope for multiple flow direction partitioning
+const MFD_EXPONENT: f64 = 1.1;
+
+/// Quinn et al. (1991) contour length of a cardinal and a diagonal neighbour's share of the cell outline, in
+/// cell widths
+const MFD_CONTOUR_LENGTHS: [f64; 2] = [0.5, 0.354];
+
+/// How flow leaving a cell is shared among its lower neighbours
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlowRouting {
+  /// All flow to the steepest of the eight neighbours
+  #[default]
+  D8,
+  /// Tarboton (1997): flow along the steepest direction of the eight triangular facets, split between the two
+  /// neighbours bounding it by angular proximity
+  DInfinity,
+  /// Multiple flow direction: shared among every lower neighbour by slope to the power 1.1 (Freeman 1991),
+  /// weighted by contour length (Quinn et al. 1991)
+  Mfd,
+}
+
+impl FromStr for FlowRouting {
+  type Err = PathfinderError;
+
+  /// Parse `d8`, `d-infinity` or `mfd`
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase()))
+      .map_err(|_| PathfinderError::UnknownFlowRouting { name: name.to_string() })
+  }
+}
+
+/// Heap entry ordering cells by a priority such as elevation; `BinaryHeap` pops the highest first, so wrap it
+/// in `Reverse` to pop the lowest
+pub(crate) struct PriorityCell {
+  pub priority: f64,
+  pub index: usize,
+}
+
+impl PartialEq for PriorityCell {
+  fn eq(&self, other: &Self) -> bool {
+    self.priority.total_cmp(&other.priority) == Ordering::Equal
+  }
+}
+
+impl Eq for PriorityCell {}
+
+impl PartialOrd for PriorityCell {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for PriorityCell {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.priority.total_cmp(&other.priority)
+  }
+}
+
+/// D8 direction code of a neighbour offset
+pub(crate) fn direction(dx: isize, dy: isize) -> usize {
+  NEIGHBORS.iter().position(|&offset| offset == (dx, dy)).expect("not a neighbour offset")
+}
+
+/// Index of each in-bounds neighbour of (x, y), with its offset
+pub(crate) fn neighbors(width: usize, height: usize, x: usize, y: usize) -> impl Iterator<Item = (usize, (isize, isize))> {
+  NEIGHBORS.into_iter().filter_map(move |(dx, dy)| {
+    let nx: usize = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+    let ny: usize = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+    Some((ny * width + nx, (dx, dy)))
+  })
+}
+
+/// Raise every closed depression to its spill elevation with Priority-Flood (Barnes et al. 2014), so each cell
+/// has a non-ascending path to the raster edge or a void. With `epsilon`, filled cells instead rise by
+/// `epsilon` metres per cell away from the spill point, leaving flats a slight gradient that D8 routing can
+/// follow. Voids stay nodata and act as outlets, like the edges.
+pub fn fill_depressions(elevations: &Raster<f64>, epsilon: Option<f64>) -> Raster<f64> {
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  let mut filled: Raster<f64> = elevations.clone();
+
+  // Seed with every cell that can drain off the raster or into a void
+  let mut closed: Vec<bool> = vec![false; width * height];
+  let mut open: BinaryHeap<Reverse<PriorityCell>> = BinaryHeap::new();
+  for y in 0..height {
+    for x in 0..width {
+      let index: usize = y * width + x;
+      if elevations.is_nodata(x, y) {
+        closed[index] = true;
+        continue;
+      }
+      let outlet: bool = x == 0
+        || y == 0
+        || x == width - 1
+        || y == height - 1
+        || neighbors(width, height, x, y).any(|(neighbor, _)| elevations.is_nodata_value(elevations.data()[neighbor]));
+      if outlet {
+        closed[index] = true;
+        open.push(Reverse(PriorityCell { priority: elevations[(x, y)], index }));
+      }
+    }
+  }
+
+  while let Some(Reverse(PriorityCell { priority: elevation, index })) = open.pop() {
+    let spill: f64 = elevation + epsilon.unwrap_or(0.0);
+    for (neighbor, _) in neighbors(width, height, index % width, index / width) {
+      if closed[neighbor] {
+        continue;
+      }
+      closed[neighbor] = true;
+      let value: &mut f64 = &mut filled.data_mut()[neighbor];
+      if *value < spill {
+        *value = spill;
+      }
+      open.push(Reverse(PriorityCell { priority: *value, index: neighbor }));
+    }
+  }
+
+  filled
+}
+
+/// Compute D8 flow directions for each cell.
+/// Returns a 2D array where each value encodes the direction to the steepest downhill neighbor:
+///   0=N, 1=NE, 2=E, 3=SE, 4=S, 5=SW, 6=W, 7=NW, 255=flat/sink (no downhill neighbor)
+/// Edge cells only look at their neighbours inside the raster, and are 255 like sinks where none is lower.
+/// Voids are 255. Run `fill_depressions` with an epsilon first so every inner cell drains.
+pub fn compute_d8_flow_directions(elevations: &Raster<f64>) -> Raster<u8> {
+  let height = elevations.height();
+  let width = elevations.width();
+  
+  let mut flow_dir: Raster<u8> = elevations.like(255);
+  
+  // D8 neighbor offsets: (dy, dx) for directions 0-7
+  // Direction encoding: 0=N, 1=NE, 2=E, 3=SE, 4=S, 5=SW, 6=W, 7=NW
+  const D8_OFFSETS: [(isize, isize); 8] = [
+    (-1, 0),  // 0: N
+    (-1, 1),  // 1: NE
+    (0, 1),   // 2: E
+    (1, 1),   // 3: SE
+    (1, 0),   // 4: S
+    (1, -1),  // 5: SW
+    (0, -1),  // 6: W
+    (-1, -1), // 7: NW
+  ];
+  
+  for i in 0..height {
+    // Ground distance to each neighbour, from this row's pixel spacing
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(i);
+    let diagonal: f64 = spacing_x.hypot(spacing_y);
+    let d8_distances: [f64; 8] = [spacing_y, diagonal, spacing_x, diagonal, spacing_y, diagonal, spacing_x, diagonal];
+
+    for j in 0..width {
+      // Voids have no flow, and nothing flows into them
+      if elevations.is_nodata(j, i) {
+        continue;
+      }
+      let center_elev = elevations[(j, i)];
+      let mut steepest_slope = 0.0;
+      let mut steepest_dir: u8 = 255;
+      
+      for (dir, &(dy, dx)) in D8_OFFSETS.iter().enumerate() {
+        let (Some(ny), Some(nx)) = (
+          i.checked_add_signed(dy).filter(|&ny| ny < height),
+          j.checked_add_signed(dx).filter(|&nx| nx < width),
+        ) else {
+          continue;
+        };
+        if elevations.is_nodata(nx, ny) {
+          continue;
+        }
+        
+        let neighbor_elev = elevations[(nx, ny)];
+        let drop = center_elev - neighbor_elev;
+        
+        if drop > 0.0 {
+          // Slope = drop / distance (accounting for diagonal distance)
+          let slope = drop / d8_distances[dir];
+          if slope > steepest_slope {
+            steepest_slope = slope;
+            steepest_dir = dir as u8;
+          }
+        }
+      }
+      
+      flow_dir[(j, i)] = steepest_dir;
+    }
+  }
+  
+  flow_dir
+}
+
+/// Index of the cell that (x, y) drains into, or `None` for sinks, edge cells with no lower neighbour and voids
+pub(crate) fn downstream(flow_directions: &Raster<u8>, x: usize, y: usize) -> Option<usize> {
+  let (dx, dy) = *NEIGHBORS.get(flow_directions[(x, y)] as usize)?;
+  let nx: usize = x.checked_add_signed(dx).filter(|&nx| nx < flow_directions.width())?;
+  let ny: usize = y.checked_add_signed(dy).filter(|&ny| ny < flow_directions.height())?;
+  Some(ny * flow_directions.width() + nx)
+}
+
+/// Cell indices ordered so every cell comes after all the cells draining into it
+fn upstream_first(flow_directions: &Raster<u8>) -> Vec<usize> {
+  let width: usize = flow_directions.width();
+  let receivers: Vec<Option<usize>> = (0..width * flow_directions.height())
+    .map(|index| downstream(flow_directions, index % width, index / width))
+    .collect();
+  let mut inflows: Vec<usize> = vec![0; receivers.len()];
+  for &receiver in receivers.iter().flatten() {
+    inflows[receiver] += 1;
+  }
+
+  let mut order: Vec<usize> = (0..receivers.len()).filter(|&index| inflows[index] == 0).collect();
+  let mut next: usize = 0;
+  while next < order.len() {
+    if let Some(receiver) = receivers[order[next]] {
+      inflows[receiver] -= 1;
+      if inflows[receiver] == 0 {
+        order.push(receiver);
+      }
+    }
+    next += 1;
+  }
+  order
+}
+
+/// Ground area in m² of each cell, and `DERIVED_NODATA` on voids
+fn cell_areas(elevations: &Raster<f64>) -> Raster<f64> {
+  let mut areas: Raster<f64> = elevations.like(0.0);
+  for y in 0..elevations.height() {
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(y);
+    for x in 0..elevations.width() {
+      areas[(x, y)] = if elevations.is_nodata(x, y) { DERIVED_NODATA } else { spacing_x * spacing_y };
+    }
+  }
+  if elevations.nodata().is_some() {
+    areas.set_nodata(Some(DERIVED_NODATA));
+  }
+  areas
+}
+
+/// Upslope contributing area in m² of each cell along `flow_directions`, including the cell itself.
+/// Voids are `DERIVED_NODATA`.
+pub fn compute_flow_accumulation(elevations: &Raster<f64>, flow_directions: &Raster<u8>) -> Raster<f64> {
+  let width: usize = elevations.width();
+  let mut accumulation: Raster<f64> = cell_areas(elevations);
+  for index in upstream_first(flow_directions) {
+    if let Some(receiver) = downstream(flow_directions, index % width, index / width) {
+      let area: f64 = accumulation.data()[index];
+      accumulation.data_mut()[receiver] += area;
+    }
+  }
+  accumulation
+}
+
+/// Strahler order of each cell whose contributing area reaches `threshold_area` m², and 0 elsewhere.
+/// Headwater streams are order 1, and two joining streams of the same order start one of the next order.
+/// Voids stay nodata.
+pub fn compute_stream_orders(
+  flow_directions: &Raster<u8>,
+  flow_accumulation: &Raster<f64>,
+  threshold_area: f64,
+) -> Raster<f64> {
+  let width: usize = flow_directions.width();
+  let mut orders: Raster<f64> =
+    flow_accumulation.map(|area| if flow_accumulation.is_nodata_value(area) { DERIVED_NODATA } else { 0.0 });
+  orders.set_nodata(flow_accumulation.nodata());
+  // Highest order draining into each cell, and how many inflowing streams carry it
+  let mut highest_inflow: Vec<(u32, u32)> = vec![(0, 0); orders.data().len()];
+
+  for index in upstream_first(flow_directions) {
+    if flow_accumulation.data()[index] < threshold_area || orders.is_nodata_value(orders.data()[index]) {
+      continue;
+    }
+    let order: u32 = match highest_inflow[index] {
+      (0, _) => 1,
+      (highest, count) if count >= 2 => highest + 1,
+      (highest, _) => highest,
+    };
+    orders.data_mut()[index] = order as f64;
+
+    if let Some(receiver) = downstream(flow_directions, index % width, index / width) {
+      let inflow: &mut (u32, u32) = &mut highest_inflow[receiver];
+      match order.cmp(&inflow.0) {
+        Ordering::Greater => *inflow = (order, 1),
+        Ordering::Equal => inflow.1 += 1,
+        Ordering::Less => {}
+      }
+    }
+  }
+  orders
+}
+
+/// Share of each cell's flow passed to each neighbour, indexed by D8 direction code. Shares sum to 1, except
+/// on voids, sinks and edge cells with no lower neighbour inside the raster, which pass nothing on; flow only
+/// ever goes to lower neighbours.
+pub fn compute_flow_partitions(elevations: &Raster<f64>, routing: FlowRouting) -> Vec<[f64; 8]> {
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  let mut partitions: Vec<[f64; 8]> = vec![[0.0; 8]; width * height];
+
+  if routing == FlowRouting::D8 {
+    for (partition, &direction) in partitions.iter_mut().zip(compute_d8_flow_directions(elevations).data()) {
+      if let Some(share) = partition.get_mut(direction as usize) {
+        *share = 1.0;
+      }
+    }
+    return partitions;
+  }
+
+  for y in 0..height {
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(y);
+    let diagonal: f64 = spacing_x.hypot(spacing_y);
+    for x in 0..width {
+      if elevations.is_nodata(x, y) {
+        continue;
+      }
+      let center: f64 = elevations[(x, y)];
+      // Elevation of the neighbour in each direction, or `None` on a void or off the raster
+      let neighbor: [Option<f64>; 8] = NEIGHBORS.map(|(dx, dy)| {
+        let nx: usize = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+        let ny: usize = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+        Some(elevations[(nx, ny)]).filter(|&value| !elevations.is_nodata_value(value))
+      });
+      let partition: &mut [f64; 8] = &mut partitions[y * width + x];
+
+      match routing {
+        FlowRouting::D8 => unreachable!("D8 partitions are returned above"),
+        FlowRouting::DInfinity => {
+          let mut steepest: f64 = 0.0;
+          for (cardinal, diagonal_direction) in FACETS {
+            let (Some(e1), Some(e2)) = (neighbor[cardinal], neighbor[diagonal_direction]) else {
+              continue;
+            };
+            // Run to the cardinal neighbour, then from it across to the diagonal one
+            let (d1, d2) = if cardinal % 4 == 0 { (spacing_y, spacing_x) } else { (spacing_x, spacing_y) };
+            let (s1, s2) = ((center - e1) / d1, (e1 - e2) / d2);
+            let facet_angle: f64 = d2.atan2(d1);
+            let (angle, slope) = match s2.atan2(s1) {
+              angle if angle < 0.0 => (0.0, s1),
+              angle if angle > facet_angle => (facet_angle, (center - e2) / diagonal),
+              angle => (angle, s1.hypot(s2)),
+            };
+            if slope > steepest {
+              steepest = slope;
+              *partition = [0.0; 8];
+              partition[diagonal_direction] = angle / facet_angle;
+              partition[cardinal] = 1.0 - angle / facet_angle;
+            }
+          }
+        }
+        FlowRouting::Mfd => {
+          for (direction, share) in partition.iter_mut().enumerate() {
+            let Some(value) = neighbor[direction] else {
+              continue;
+            };
+            let distance: f64 = match direction {
+              0 | 4 => spacing_y,
+              2 | 6 => spacing_x,
+              _ => diagonal,
+            };
+            let drop: f64 = center - value;
+            if drop > 0.0 {
+              *share = (drop / distance).powf(MFD_EXPONENT) * MFD_CONTOUR_LENGTHS[direction % 2];
+            }
+          }
+          let total: f64 = partition.iter().sum();
+          if total > 0.0 {
+            partition.iter_mut().for_each(|share| *share /= total);
+          }
+        }
+      }
+    }
+  }
+  partitions
+}
+
+/// Indices of the cells with elevations, highest first, which puts every cell after all the cells that can
+/// pass flow to it
+pub(crate) fn highest_first(elevations: &Raster<f64>) -> Vec<usize> {
+  let mut order: Vec<usize> =
+    (0..elevations.data().len()).filter(|&index| !elevations.is_nodata_value(elevations.data()[index])).collect();
+  order.sort_by(|&a, &b| elevations.data()[b].total_cmp(&elevations.data()[a]));
+  order
+}
+
+/// Upslope contributing area in m² of each cell, sharing each cell's flow among its neighbours with
+/// `routing`. Voids are `DERIVED_NODATA`.
+pub fn compute_flow_accumulation_with_routing(elevations: &Raster<f64>, routing: FlowRouting) -> Raster<f64> {
+  let width: usize = elevations.width();
+  let partitions: Vec<[f64; 8]> = compute_flow_partitions(elevations, routing);
+  let mut accumulation: Raster<f64> = cell_areas(elevations);
+  for index in highest_first(elevations) {
+    let area: f64 = accumulation.data()[index];
+    for (receiver, (dx, dy)) in neighbors(width, elevations.height(), index % width, index / width) {
+      let share: f64 = partitions[index][direction(dx, dy)];
+      if share > 0.0 {
+        accumulation.data_mut()[receiver] += area * share;
+      }
+    }
+  }
+  accumulation
+}
+
+/// Drainage of a DEM and the streams extracted from it
+pub struct StreamNetwork {
+  /// D8 directions along which the stream links are traced
+  pub flow_directions: Raster<u8>,
+  /// Upslope contributing area in m²
+  pub flow_accumulation: Raster<f64>,
+  /// Strahler order of stream cells, 0 elsewhere
+  pub stream_orders: Raster<f64>,
+}
+
+/// `extract_stream_network_with_routing` using D8 flow accumulation
+pub fn extract_stream_network(elevations: &Raster<f64>, threshold_area: f64) -> StreamNetwork {
+  extract_stream_network_with_routing(elevations, threshold_area, FlowRouting::D8)
+}
+
+/// Fill depressions, accumulate flow with `routing` and extract the streams draining at least `threshold_area`
+/// m². Dispersive routing keeps hillslopes below the threshold longer, while stream links and their Strahler
+/// orders always follow D8 directions.
+pub fn extract_stream_network_with_routing(
+  elevations: &Raster<f64>,
+  threshold_area: f64,
+  routing: FlowRouting,
+) -> StreamNetwork {
+  let filled: Raster<f64> = fill_depressions(elevations, Some(FILL_EPSILON));
+  let flow_directions: Raster<u8> = compute_d8_flow_directions(&filled);
+  let flow_accumulation: Raster<f64> = match routing {
+    FlowRouting::D8 => compute_flow_accumulation(elevations, &flow_directions),
+    _ => compute_flow_accumulation_with_routing(&filled, routing),
+  };
+  let stream_orders: Raster<f64> = compute_stream_orders(&flow_directions, &flow_accumulation, threshold_area);
+  StreamNetwork { flow_directions, flow_accumulation, stream_orders }
+}
+
+/// Serialize the streams as a GeoJSON FeatureCollection of LineStrings, one per link between a stream head or
+/// confluence and the next confluence or outlet, each with its Strahler `order`
+pub fn streams_to_geojson(network: &StreamNetwork) -> String {
+  let StreamNetwork { flow_directions, stream_orders, .. } = network;
+  let width: usize = stream_orders.width();
+  let is_stream = |index: usize| -> bool { stream_orders.data()[index] > 0.0 };
+
+  let mut stream_inflows: Vec<u32> = vec![0; stream_orders.data().len()];
+  for index in (0..stream_inflows.len()).filter(|&index| is_stream(index)) {
+    if let Some(receiver) = downstream(flow_directions, index % width, index / width) {
+      stream_inflows[receiver] += 1;
+    }
+  }
+
+  // Links start at stream heads and confluences, and run until the next confluence or the end of the stream
+  let mut features: Vec<Feature> = vec![];
+  for start in (0..stream_inflows.len()).filter(|&index| is_stream(index) && stream_inflows[index] != 1) {
+    let mut coordinates: Vec<Vec<f64>> = vec![];
+    let mut current: usize = start;
+    loop {
+      let coordinate = stream_orders.pixel_to_coord(current % width, current / width);
+      coordinates.push(vec![coordinate.x, coordinate.y]);
+      match downstream(flow_directions, current % width, current / width) {
+        Some(next) if is_stream(next) && stream_inflows[next] == 1 => current = next,
+        // End on the confluence or the non-stream cell it drains into, so links connect
+        Some(next) => {
+          let coordinate = stream_orders.pixel_to_coord(next % width, next / width);
+          coordinates.push(vec![coordinate.x, coordinate.y]);
+          break;
+        }
+        None => break,
+      }
+    }
+    if coordinates.len() < 2 {
+      continue;
+    }
+    features.push(Feature {
+      bbox: None,
+      geometry: Some(Geometry::new(Value::LineString(coordinates))),
+      id: None,
+      properties: Some(
+        serde_json::json!({ "order": stream_orders.data()[start] as u32 }).as_object().unwrap().clone(),
+      ),
+      foreign_members: None,
+    });
+  }
+
+  FeatureCollection { features, bbox: None, foreign_members: None }.to_string()
+}
+
+/// Area draining through a pour point
+#[derive(Debug, Clone)]
+pub struct Watershed {
+  /// Cell the pour point was snapped to
+  pub pour_point: Coordinate,
+  /// 1 on cells draining through the pour point, 0 elsewhere and `DERIVED_NODATA` on voids
+  pub mask: Raster<f64>,
+  /// Contributing area in m², including the pour point cell
+  pub area: f64,
+}
+
+/// Delineate the watershed upstream of `pour_point` along D8 flow directions of the depression-filled DEM.
+/// With `snap_distance`, the pour point first moves to the cell of greatest flow accumulation within that many
+/// metres, so a point dropped next to a gully still picks up its catchment.
+pub fn delineate_watershed(
+  elevations: &Raster<f64>,
+  pour_point: Coordinate,
+  snap_distance: Option<f64>,
+) -> Result<Watershed, PathfinderError> {
+  let (x, y) = elevations
+    .coord_to_pixel(pour_point)
+    .ok_or(PathfinderError::PourPointOutOfBounds { coordinate: pour_point })?;
+  if elevations.is_nodata(x, y) {
+    return Err(PathfinderError::NoDataAtEndpoint { coordinate: pour_point });
+  }
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  let flow_directions: Raster<u8> = compute_d8_flow_directions(&fill_depressions(elevations, Some(FILL_EPSILON)));
+
+  let mut outlet: usize = y * width + x;
+  if let Some(snap_distance) = snap_distance {
+    let accumulation: Raster<f64> = compute_flow_accumulation(elevations, &flow_directions);
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(y);
+    let (reach_x, reach_y) = ((snap_distance / spacing_x) as usize, (snap_distance / spacing_y) as usize);
+    for ny in y.saturating_sub(reach_y)..=(y + reach_y).min(height - 1) {
+      for nx in x.saturating_sub(reach_x)..=(x + reach_x).min(width - 1) {
+        let distance: f64 = ((nx as f64 - x as f64) * spacing_x).hypot((ny as f64 - y as f64) * spacing_y);
+        let index: usize = ny * width + nx;
+        if distance <= snap_distance
+          && !elevations.is_nodata(nx, ny)
+          && accumulation.data()[index] > accumulation.data()[outlet]
+        {
+          outlet = index;
+        }
+      }
+    }
+  }
+
+  // Walk upstream from the outlet through every neighbour draining into a cell already in the watershed
+  let areas: Raster<f64> = cell_areas(elevations);
+  let mut mask: Raster<f64> =
+    elevations.map(|elevation| if elevations.is_nodata_value(elevation) { DERIVED_NODATA } else { 0.0 });
+  if elevations.nodata().is_some() {
+    mask.set_nodata(Some(DERIVED_NODATA));
+  }
+  mask.data_mut()[outlet] = 1.0;
+  let mut area: f64 = 0.0;
+  let mut pending: Vec<usize> = vec![outlet];
+  while let Some(index) = pending.pop() {
+    area += areas.data()[index];
+    for (neighbor, _) in neighbors(width, height, index % width, index / width) {
+      let drains_here: bool = downstream(&flow_directions, neighbor % width, neighbor / width) == Some(index);
+      if mask.data()[neighbor] == 0.0 && drains_here {
+        mask.data_mut()[neighbor] = 1.0;
+        pending.push(neighbor);
+      }
+    }
+  }
+
+  Ok(Watershed { pour_point: elevations.pixel_to_coord(outlet % width, outlet / width), mask, area })
+}
+
+/// Cell corner as (column, row) on the pixel grid, the corner above and left of a cell sharing its indices
+type Corner = (isize, isize);
+
+/// Serialize the watershed outline as a GeoJSON FeatureCollection holding one Polygon, or a MultiPolygon where
+/// parts only touch diagonally, with its `area` in m². Rings follow cell edges; voids inside become holes.
+pub fn watershed_to_geojson(watershed: &Watershed) -> String {
+  let mask: &Raster<f64> = &watershed.mask;
+  let (width, height) = (mask.width() as isize, mask.height() as isize);
+  let inside = |x: isize, y: isize| -> bool {
+    x >= 0 && y >= 0 && x < width && y < height && mask.data()[(y * width + x) as usize] == 1.0
+  };
+
+  // Boundary edges between cell corners, directed clockwise on screen so the watershed lies on their right
+  let mut edges: HashMap<Corner, Vec<Corner>> = HashMap::new();
+  for y in 0..height {
+    for x in 0..width {
+      if !inside(x, y) {
+        continue;
+      }
+      let sides: [((isize, isize), Corner, Corner); 4] = [
+        ((0, -1), (x, y), (x + 1, y)),
+        ((1, 0), (x + 1, y), (x + 1, y + 1)),
+        ((0, 1), (x + 1, y + 1), (x, y + 1)),
+        ((-1, 0), (x, y + 1), (x, y)),
+      ];
+      for ((dx, dy), from, to) in sides {
+        if !inside(x + dx, y + dy) {
+          edges.entry(from).or_default().push(to);
+        }
+      }
+    }
+  }
+
+  // Chain edges into rings, turning right where two parts touch at a corner so they stay separate rings
+  let mut rings: Vec<Vec<Corner>> = vec![];
+  let mut starts: Vec<Corner> = edges.keys().copied().collect();
+  starts.sort_unstable();
+  for start in starts {
+    while edges.get(&start).is_some_and(|targets| !targets.is_empty()) {
+      let mut ring: Vec<Corner> = vec![start];
+      let mut current: Corner = start;
+      let mut heading: (isize, isize) = (0, 0);
+      loop {
+        let targets: &mut Vec<Corner> = edges.get_mut(&current).expect("boundary edges form closed rings");
+        let right_turn: Corner = (current.0 - heading.1, current.1 + heading.0);
+        let next: usize = targets.iter().position(|&target| target == right_turn).unwrap_or(0);
+        let target: Corner = targets.swap_remove(next);
+        heading = (target.0 - current.0, target.1 - current.1);
+        current = target;
+        if current == start {
+          break;
+        }
+        ring.push(current);
+      }
+      // Keep only corners where the ring turns
+      let corners: Vec<Corner> = (0..ring.len())
+        .filter(|&i| {
+          let (previous, next) = (ring[(i + ring.len() - 1) % ring.len()], ring[(i + 1) % ring.len()]);
+          (ring[i].0 - previous.0) * (next.1 - ring[i].1) != (ring[i].1 - previous.1) * (next.0 - ring[i].0)
+        })
+        .map(|i| ring[i])
+        .collect();
+      rings.push(corners);
+    }
+  }
+
+  // Rings run clockwise on screen around outlines and anticlockwise around holes at voids, and are written in
+  // reverse so outlines run anticlockwise as RFC 7946 asks
+  let twice_area = |ring: &[Corner]| -> isize {
+    (0..ring.len()).map(|i| ring[i].0 * ring[(i + 1) % ring.len()].1 - ring[(i + 1) % ring.len()].0 * ring[i].1).sum()
+  };
+  let (outlines, holes): (Vec<Vec<Corner>>, Vec<Vec<Corner>>) =
+    rings.into_iter().partition(|ring| twice_area(ring) > 0);
+  let contains = |ring: &[Corner], (px, py): (f64, f64)| -> bool {
+    (0..ring.len()).fold(false, |inside, i| {
+      let ((x0, y0), (x1, y1)) = (ring[i], ring[(i + 1) % ring.len()]);
+      let crosses: bool = ((y0 as f64) > py) != ((y1 as f64) > py)
+        && px < x0 as f64 + (py - y0 as f64) * (x1 - x0) as f64 / (y1 - y0) as f64;
+      inside != crosses
+    })
+  };
+  let to_positions = |ring: &[Corner]| -> Vec<Vec<f64>> {
+    ring
+      .iter()
+      .rev()
+      .chain(ring.last())
+      .map(|&(x, y)| {
+        let coordinate: Coordinate = mask.center_transform().apply(x as f64 - 0.5, y as f64 - 0.5);
+        vec![coordinate.x, coordinate.y]
+      })
+      .collect()
+  };
+  let mut polygons: Vec<Vec<Vec<Vec<f64>>>> = outlines.iter().map(|outline| vec![to_positions(outline)]).collect();
+  for hole in &holes {
+    // The watershed cell to the right of the hole's first edge lies inside the outline around the hole
+    let (from, to) = (hole[0], hole[1]);
+    let (dx, dy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let cell_center: (f64, f64) =
+      (from.0 as f64 + (dx - dy) as f64 * 0.5, from.1 as f64 + (dx + dy) as f64 * 0.5);
+    let enclosing: Option<usize> = (0..outlines.len())
+      .filter(|&outline| contains(&outlines[outline], cell_center))
+      .min_by_key(|&outline| twice_area(&outlines[outline]));
+    if let Some(outline) = enclosing {
+      polygons[outline].push(to_positions(hole));
+    }
+  }
+
+  let geometry: Value = match polygons.len() {
+    1 => Value::Polygon(polygons.remove(0)),
+    _ => Value::MultiPolygon(polygons),
+  };
+  let feature: Feature = Feature {
+    bbox: None,
+    geometry: Some(Geometry::new(geometry)),
+    id: None,
+    properties: Some(serde_json::json!({ "area": watershed.area }).as_object().unwrap().clone()),
+    foreign_members: None,
+  };
+  FeatureCollection { features: vec![feature], bbox: None, foreign_members: None }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  /// Grid of 30 m pixels tagged PixelIsArea, from rows listed north to south
+  fn grid(rows: &[&[f64]]) -> Raster<f64> {
+    let crs = CrsInfo { geo_keys: vec![1, 1, 0, 1, 1025, 0, 1, 1], ..Default::default() };
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    Raster::new(rows.concat(), rows[0].len(), rows.len(), transform, crs)
+  }
+
+  /// `width` x `height` valley draining south along its middle column
+  fn valley(width: usize, height: usize) -> Raster<f64> {
+    let rows: Vec<Vec<f64>> = (0..height)
+      .map(|y| (0..width).map(|x| 100.0 + 3.0 * x.abs_diff(width / 2) as f64 + 2.0 * (height - y) as f64).collect())
+      .collect();
+    grid(&rows.iter().map(Vec::as_slice).collect::<Vec<_>>())
+  }
+
+  #[test]
+  fn priority_flood_raises_a_pit_to_its_spill_point() {
+    let pit: Raster<f64> = grid(&[
+      &[10.0, 10.0, 10.0, 10.0, 10.0],
+      &[10.0, 2.0, 2.0, 2.0, 10.0],
+      &[5.0, 2.0, 1.0, 2.0, 10.0],
+      &[10.0, 2.0, 2.0, 2.0, 10.0],
+      &[10.0, 10.0, 10.0, 10.0, 10.0],
+    ]);
+    let filled: Raster<f64> = fill_depressions(&pit, None);
+    for y in 0..5 {
+      for x in 0..5 {
+        let expected: f64 = if (1..4).contains(&x) && (1..4).contains(&y) { 5.0 } else { pit[(x, y)] };
+        assert_eq!(filled[(x, y)], expected, "({x}, {y})");
+      }
+    }
+
+    // With epsilon the filled cells rise away from the spill point, so D8 drains them all out through it
+    let filled: Raster<f64> = fill_depressions(&pit, Some(FILL_EPSILON));
+    assert!(filled[(1, 2)] > 5.0 && filled[(2, 2)] > filled[(1, 2)] && filled[(3, 2)] > filled[(2, 2)]);
+    let flow_directions: Raster<u8> = compute_d8_flow_directions(&filled);
+    let mut index: usize = 2 * 5 + 3;
+    while let Some(next) = downstream(&flow_directions, index % 5, index / 5) {
+      index = next;
+    }
+    assert_eq!(index, 2 * 5);
+  }
+
+  #[test]
+  fn strahler_order_rises_only_where_equal_orders_join() {
+    // Two headwaters joining at (1, 1), then a lesser tributary joining at (1, 2)
+    const NONE: u8 = 255;
+    let flow_directions: Raster<u8> = grid(&[&[0.0; 3][..]; 4]).with_data(vec![
+      3, NONE, 5, //
+      NONE, 4, NONE, //
+      2, 4, NONE, //
+      NONE, 4, NONE,
+    ]);
+    let accumulation: Raster<f64> = compute_flow_accumulation(&grid(&[&[0.0; 3][..]; 4]), &flow_directions);
+    assert_eq!(accumulation[(1, 3)], 6.0 * 900.0);
+
+    let orders: Raster<f64> = compute_stream_orders(&flow_directions, &accumulation, 900.0);
+    assert_eq!(orders[(0, 0)], 1.0);
+    assert_eq!(orders[(2, 0)], 1.0);
+    assert_eq!(orders[(1, 1)], 2.0);
+    assert_eq!(orders[(0, 2)], 1.0);
+    assert_eq!(orders[(1, 2)], 2.0);
+    assert_eq!(orders[(1, 3)], 2.0);
+
+    // Headwaters below the threshold aren't streams, so the trunk starts over at order 1
+    let orders: Raster<f64> = compute_stream_orders(&flow_directions, &accumulation, 2000.0);
+    assert_eq!(orders[(0, 0)], 0.0);
+    assert_eq!(orders[(1, 1)], 1.0);
+    assert_eq!(orders[(1, 3)], 1.0);
+  }
+
+  #[test]
+  fn flow_partitions_share_all_flow_among_lower_neighbours() {
+    // Plane falling 0.4 per metre to the north and 0.3 to the west
+    let rows: Vec<Vec<f64>> =
+      (0..5).map(|y| (0..5).map(|x| 100.0 + 9.0 * x as f64 + 12.0 * y as f64).collect()).collect();
+    let plane: Raster<f64> = grid(&rows.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    let center: usize = 2 * 5 + 2;
+
+    // D-infinity splits between north and north-west by the angle of the fall line within the facet
+    let d_infinity: [f64; 8] = compute_flow_partitions(&plane, FlowRouting::DInfinity)[center];
+    let north_west: f64 = 0.75f64.atan() / std::f64::consts::FRAC_PI_4;
+    assert!((d_infinity[7] - north_west).abs() < 1e-12);
+    assert!((d_infinity[0] - (1.0 - north_west)).abs() < 1e-12);
+
+    for routing in [FlowRouting::D8, FlowRouting::DInfinity, FlowRouting::Mfd] {
+      let partitions: Vec<[f64; 8]> = compute_flow_partitions(&plane, routing);
+      assert!((partitions[center].iter().sum::<f64>() - 1.0).abs() < 1e-12, "{routing:?}");
+      for (neighbor, (dx, dy)) in neighbors(5, 5, 2, 2) {
+        if plane.data()[neighbor] >= plane.data()[center] {
+          assert_eq!(partitions[center][direction(dx, dy)], 0.0, "{routing:?} sends flow uphill");
+        }
+      }
+      // The lowest corner has nowhere to go inside the raster
+      assert_eq!(partitions[0], [0.0; 8], "{routing:?}");
+    }
+  }
+
+  /// Polygons of a watershed's GeoJSON outline, as rings of [x, y] positions
+  fn outline_polygons(watershed: &Watershed) -> Vec<Vec<Vec<Vec<f64>>>> {
+    let geojson: geojson::GeoJson = watershed_to_geojson(watershed).parse().unwrap();
+    let geojson::GeoJson::FeatureCollection(collection) = geojson else {
+      panic!("expected a FeatureCollection");
+    };
+    match collection.features[0].geometry.as_ref().unwrap().value.clone() {
+      Value::Polygon(polygon) => vec![polygon],
+      Value::MultiPolygon(polygons) => polygons,
+      value => panic!("expected polygons, got {value:?}"),
+    }
+  }
+
+  /// Twice the signed area of a closed ring, positive when it runs anticlockwise with y pointing north
+  fn twice_signed_area(ring: &[Vec<f64>]) -> f64 {
+    ring.windows(2).map(|pair| pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1]).sum()
+  }
+
+  #[test]
+  fn valley_watershed_outline_follows_the_raster_edge_around_a_void() {
+    let mut elevations: Raster<f64> = valley(7, 6);
+    elevations.set_nodata(Some(-9999.0));
+    elevations[(1, 2)] = -9999.0;
+    let outlet: Coordinate = elevations.pixel_to_coord(3, 5);
+    let watershed: Watershed = delineate_watershed(&elevations, outlet, None).unwrap();
+    assert_eq!(watershed.area, (7.0 * 6.0 - 1.0) * 900.0);
+    assert_eq!(watershed.mask[(1, 2)], DERIVED_NODATA);
+
+    let polygons = outline_polygons(&watershed);
+    assert_eq!(polygons.len(), 1);
+    let [outline, hole] = polygons[0].as_slice() else {
+      panic!("expected an outline and one hole, got {:?}", polygons[0]);
+    };
+    // Cell edges of a PixelIsArea raster line up with its extent
+    assert_eq!(outline.len(), 5);
+    for position in outline {
+      assert!([500_000.0, 500_210.0].contains(&position[0]) && [3_999_820.0, 4_000_000.0].contains(&position[1]));
+    }
+    assert_eq!(outline.first(), outline.last());
+    assert_eq!(twice_signed_area(outline), 2.0 * 7.0 * 6.0 * 900.0);
+    assert_eq!(twice_signed_area(hole), -2.0 * 900.0);
+  }
+
+  #[test]
+  fn watershed_parts_touching_at_a_corner_are_separate_polygons() {
+    let elevations: Raster<f64> = grid(&[&[0.0; 3][..]; 3]);
+    let mask: Raster<f64> = elevations.with_data(vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    let watershed = Watershed { pour_point: elevations.pixel_to_coord(1, 1), mask, area: 1800.0 };
+    let polygons = outline_polygons(&watershed);
+    assert_eq!(polygons.len(), 2);
+    for polygon in polygons {
+      assert_eq!(polygon.len(), 1);
+      assert_eq!(twice_signed_area(&polygon[0]), 2.0 * 900.0);
+    }
+  }
+}
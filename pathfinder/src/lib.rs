@@ -1,18 +1,51 @@
-use wasm_bindgen::prelude::*;
-
 mod azimuth;
+mod classify;
 mod console_log;
+mod curvature;
+mod error;
 mod find_path;
+mod gdal;
 mod geotiff;
+mod hillshade;
+mod hydrology;
+mod overhead;
 mod raster;
+mod raw;
+mod runout;
+mod stack;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-pub use azimuth::{compute_azimuths, Aspect, AzimuthResult};
-pub use find_path::find_path_rs;
-pub use geotiff::serialize_to_geotiff;
-pub use raster::get_raster;
-
-// Initialize panic hook for better error messages in browser console
-#[wasm_bindgen]
-pub fn init() {
-    console_error_panic_hook::set_once();
-}
\ No newline at end of file
+pub use azimuth::{
+  analyze_slopes, analyze_terrain, analyze_terrain_with_options, calculate_azimuth, compute_runout_zones,
+  AnalysisOptions, Aspect, SlopeAlgorithm, SlopeAnalysis, TerrainAnalysis, DERIVED_NODATA,
+};
+pub use classify::{classify_slopes, rate_terrain, AtesRating, SlopeClass};
+pub use curvature::{compute_curvatures, Curvatures};
+pub use error::PathfinderError;
+pub use find_path::{
+  find_path, path_to_geojson, path_to_geojson_with_exposure, path_to_gpx, ExplorationCallback, PathOptions, PathPoint,
+  RouteLayers,
+};
+pub use geotiff::{
+  serialize_bands_to_geotiff, serialize_to_geotiff, serialize_to_geotiff_with_options, GeoTiffCompression, GeoTiffOptions,
+};
+pub use georaster::Coordinate;
+pub use hillshade::{compute_hillshade, HillshadeOptions};
+pub use hydrology::{
+  compute_d8_flow_directions, compute_flow_accumulation, compute_flow_accumulation_with_routing,
+  compute_flow_partitions, compute_stream_orders, delineate_watershed, extract_stream_network,
+  extract_stream_network_with_routing, fill_depressions, streams_to_geojson, watershed_to_geojson, FlowRouting,
+  StreamNetwork, Watershed, DEFAULT_STREAM_THRESHOLD_AREA,
+};
+pub use overhead::{OverheadExposure, OverheadHazard};
+pub use raster::{get_raster, read_geotiff, read_geotiff_bands, CrsInfo, GeoTransform, Raster, RasterType};
+pub use raw::{is_raw_raster, read_raw, serialize_to_raw, RAW_RASTER_MAGIC};
+pub use runout::{RunoutConfig, RunoutModel, DEFAULT_ALPHA_ANGLE};
+pub use stack::{TerrainSample, TerrainStack};
+#[cfg(feature = "wasm")]
+pub use wasm::{
+  compute_azimuths, compute_azimuths_raw, compute_hillshade_rs, compute_runout_zones_rs, compute_streams_rs,
+  compute_terrain_classes_rs, compute_terrain_stack, delineate_watershed_rs, fill_depressions_rs, find_path_rs,
+  find_path_stack_rs, init, AzimuthResult, StreamResult, Terrain, TerrainClassResult, WatershedResult,
+};
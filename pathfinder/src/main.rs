@@ -0,0 +1,490 @@
+use std::{
+  error::Error,
+  fs::{self, File},
+  io::BufReader,
+  path::{Path, PathBuf},
+  process::ExitCode,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use pathfinder::{
+  analyze_terrain_with_options, classify_slopes, compute_hillshade, delineate_watershed,
+  extract_stream_network_with_routing, fill_depressions, path_to_geojson_with_exposure, path_to_gpx, read_geotiff,
+  serialize_to_geotiff_with_options, streams_to_geojson, watershed_to_geojson, AnalysisOptions, Aspect, AtesRating,
+  Coordinate, FlowRouting, GeoTiffCompression, GeoTiffOptions, HillshadeOptions, OverheadExposure, PathOptions,
+  PathfinderError, Raster, RunoutConfig, RunoutModel, SlopeAlgorithm, StreamNetwork, TerrainAnalysis, TerrainStack,
+  Watershed, DEFAULT_STREAM_THRESHOLD_AREA,
+};
+
+/// Terrain analysis and routing on DEM GeoTIFFs
+#[derive(Parser)]
+#[command(name = "pathfinder", version)]
+struct Cli {
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Compute azimuth, gradient, runout zone, curvature, slope class and ATES rating rasters from a DEM
+  Analyze {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Directory for the derived GeoTIFFs
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+    /// Aspect whose steep slopes are avalanche start zones (repeatable)
+    #[arg(long = "exclude-aspect", value_parser = parse_aspect)]
+    excluded_aspects: Vec<Aspect>,
+    /// Slope algorithm: horn, zevenbergen-thorne, evans-young or sobel5x5
+    #[arg(long, value_parser = parse_slope_algorithm, default_value = "sobel5x5")]
+    algorithm: SlopeAlgorithm,
+    /// Trace runout with the alpha-angle model, reaching this many degrees below the start zone, instead of
+    /// fixed-length D8 paths
+    #[arg(long, value_parser = parse_alpha_angle)]
+    alpha_angle: Option<RunoutModel>,
+    /// JSON file overriding any of the start zone and runout parameters
+    #[arg(long)]
+    runout_config: Option<PathBuf>,
+    /// Compression of the derived GeoTIFFs; Deflate and LZW use the floating-point predictor
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+    /// Write square internal tiles of this size (a multiple of 16) instead of strips
+    #[arg(long)]
+    tile_size: Option<u32>,
+    /// Append reduced-resolution overviews
+    #[arg(long)]
+    overviews: bool,
+    /// Write the elevations and derived layers as the named bands of a single terrain.tif
+    #[arg(long)]
+    stack: bool,
+  },
+  /// Render shaded relief from a DEM
+  Hillshade {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Output GeoTIFF
+    #[arg(short, long, default_value = "hillshade.tif")]
+    output: PathBuf,
+    /// Direction the light comes from, in degrees clockwise from north
+    #[arg(long, default_value_t = 315.0)]
+    azimuth: f64,
+    /// Angle of the light above the horizon, in degrees
+    #[arg(long, default_value_t = 45.0)]
+    altitude: f64,
+    /// Vertical exaggeration
+    #[arg(long, default_value_t = 1.0)]
+    z_factor: f64,
+    /// Blend light from the west through north instead of a single azimuth
+    #[arg(long)]
+    multidirectional: bool,
+    /// Slope algorithm: horn, zevenbergen-thorne, evans-young or sobel5x5
+    #[arg(long, value_parser = parse_slope_algorithm, default_value = "horn")]
+    algorithm: SlopeAlgorithm,
+    /// Compression of the GeoTIFF; Deflate and LZW use the floating-point predictor
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+  },
+  /// Fill the depressions of a DEM so every cell drains to the edge or a void
+  Fill {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Output GeoTIFF
+    #[arg(short, long, default_value = "filled.tif")]
+    output: PathBuf,
+    /// Rise in metres per cell across filled depressions, so flats drain; left level if omitted
+    #[arg(long)]
+    epsilon: Option<f64>,
+    /// Compression of the GeoTIFF; Deflate and LZW use the floating-point predictor
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+  },
+  /// Compute flow accumulation and Strahler stream orders, and trace the stream network
+  Streams {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Directory for flow_accumulation.tif, stream_orders.tif and streams.geojson
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+    /// Upslope area in m² at which a drainage counts as a stream
+    #[arg(long, default_value_t = DEFAULT_STREAM_THRESHOLD_AREA)]
+    threshold_area: f64,
+    /// Flow routing for the accumulation: d8, d-infinity or mfd
+    #[arg(long, value_parser = parse_flow_routing, default_value = "d8")]
+    routing: FlowRouting,
+    /// Compression of the GeoTIFFs; Deflate and LZW use the floating-point predictor
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+  },
+  /// Delineate the area draining through a pour point
+  Watershed {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Pour point as lon,lat
+    #[arg(long, value_parser = parse_lon_lat, allow_hyphen_values = true)]
+    pour_point: Coordinate,
+    /// Move the pour point to the cell of greatest flow accumulation within this many metres
+    #[arg(long)]
+    snap_distance: Option<f64>,
+    /// Directory for watershed.tif and watershed.geojson
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+    /// Compression of the GeoTIFF; Deflate and LZW use the floating-point predictor
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+  },
+  /// Find the lowest-cost path between two points
+  Route {
+    /// Input DEM GeoTIFF
+    dem: PathBuf,
+    /// Start point as lon,lat
+    #[arg(long, value_parser = parse_lon_lat, allow_hyphen_values = true)]
+    start: Coordinate,
+    /// End point as lon,lat
+    #[arg(long, value_parser = parse_lon_lat, allow_hyphen_values = true)]
+    end: Coordinate,
+    /// Steepest uphill rise/run allowed between neighboring pixels
+    #[arg(long)]
+    max_gradient: Option<f64>,
+    /// Aspect to avoid on slopes steeper than --aspect-gradient-threshold (repeatable)
+    #[arg(long = "exclude-aspect", value_parser = parse_aspect)]
+    excluded_aspects: Vec<Aspect>,
+    #[arg(long)]
+    aspect_gradient_threshold: Option<f64>,
+    /// Most severe ATES rating allowed on the route: simple, challenging or complex
+    #[arg(long, value_parser = parse_ates_rating)]
+    max_ates_rating: Option<AtesRating>,
+    /// Treat streams of this Strahler order and higher as impassable
+    #[arg(long)]
+    stream_barrier_order: Option<u32>,
+    /// Extra cost, in metres of level walking, of each step onto a smaller stream
+    #[arg(long)]
+    stream_crossing_cost: Option<f64>,
+    /// Upslope area in m² at which a drainage counts as a stream for the stream options
+    #[arg(long)]
+    stream_threshold_area: Option<f64>,
+    /// Extra cost, in metres of level walking, of each step per hectare of start zone on the excluded aspects
+    /// that can reach it
+    #[arg(long)]
+    overhead_exposure_cost: Option<f64>,
+    /// Add the start zones on the excluded aspects above each point to the GeoJSON output
+    #[arg(long)]
+    report_overhead_exposure: bool,
+    /// Slope algorithm: horn, zevenbergen-thorne, evans-young or sobel5x5
+    #[arg(long, value_parser = parse_slope_algorithm, default_value = "sobel5x5")]
+    algorithm: SlopeAlgorithm,
+    /// Trace runout with the alpha-angle model, reaching this many degrees below the start zone, instead of
+    /// fixed-length D8 paths
+    #[arg(long, value_parser = parse_alpha_angle)]
+    alpha_angle: Option<RunoutModel>,
+    /// JSON file overriding any of the start zone and runout parameters
+    #[arg(long)]
+    runout_config: Option<PathBuf>,
+    /// Treat runout zones below excluded aspects as impassable
+    #[arg(long)]
+    avoid_runout_zones: bool,
+    /// Output file; written to stdout if omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Output format; inferred from the output extension if omitted
+    #[arg(short, long)]
+    format: Option<RouteFormat>,
+  },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Compression {
+  None,
+  Lzw,
+  Deflate,
+}
+
+impl From<Compression> for GeoTiffCompression {
+  fn from(compression: Compression) -> Self {
+    match compression {
+      Compression::None => GeoTiffCompression::None,
+      Compression::Lzw => GeoTiffCompression::Lzw,
+      Compression::Deflate => GeoTiffCompression::Deflate,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RouteFormat {
+  Geojson,
+  Gpx,
+}
+
+fn parse_lon_lat(value: &str) -> Result<Coordinate, String> {
+  let (lon, lat) = value.split_once(',').ok_or("expected lon,lat")?;
+  let lon: f64 = lon.trim().parse().map_err(|e| format!("invalid longitude: {}", e))?;
+  let lat: f64 = lat.trim().parse().map_err(|e| format!("invalid latitude: {}", e))?;
+  Ok(Coordinate::new(lat, lon))
+}
+
+fn parse_aspect(value: &str) -> Result<Aspect, String> {
+  serde_json::from_value(serde_json::Value::String(value.to_lowercase())).map_err(|_| format!("unknown aspect: {}", value))
+}
+
+fn parse_slope_algorithm(value: &str) -> Result<SlopeAlgorithm, String> {
+  value.parse().map_err(|e: PathfinderError| e.to_string())
+}
+
+fn parse_ates_rating(value: &str) -> Result<AtesRating, String> {
+  value.parse().map_err(|e: PathfinderError| e.to_string())
+}
+
+fn parse_flow_routing(value: &str) -> Result<FlowRouting, String> {
+  value.parse().map_err(|e: PathfinderError| e.to_string())
+}
+
+fn parse_alpha_angle(value: &str) -> Result<RunoutModel, String> {
+  let alpha: f64 = value.parse().map_err(|e| format!("invalid alpha angle: {}", e))?;
+  RunoutModel::alpha_angle(alpha).map_err(|e| e.to_string())
+}
+
+/// `RunoutConfig` from a JSON file, or the defaults if no file is given
+fn read_runout_config(path: Option<&Path>) -> Result<RunoutConfig, Box<dyn Error>> {
+  let Some(path) = path else {
+    return Ok(RunoutConfig::default());
+  };
+  let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+  Ok(
+    serde_json::from_reader(BufReader::new(file))
+      .map_err(|e| PathfinderError::InvalidRunoutConfig { message: e.to_string() })?,
+  )
+}
+
+fn read_dem(path: &Path) -> Result<Raster<f64>, Box<dyn Error>> {
+  let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+  Ok(read_geotiff(BufReader::new(file))?)
+}
+
+fn analyze(
+  dem: &Path,
+  output_dir: &Path,
+  excluded_aspects: &[Aspect],
+  analysis_options: AnalysisOptions,
+  geotiff_options: &GeoTiffOptions,
+  stack: bool,
+) -> Result<(), Box<dyn Error>> {
+  let elevations: Raster<f64> = read_dem(dem)?;
+  fs::create_dir_all(output_dir)?;
+  if stack {
+    let path: PathBuf = output_dir.join("terrain.tif");
+    fs::write(&path, TerrainStack::with_options(elevations, excluded_aspects, analysis_options).to_geotiff(geotiff_options)?)?;
+    eprintln!("Wrote {}", path.display());
+    return Ok(());
+  }
+
+  let TerrainAnalysis { azimuths, gradients, runout_zones, curvatures, ates_ratings } =
+    analyze_terrain_with_options(&elevations, excluded_aspects, &analysis_options);
+  let slope_classes: Raster<f64> = classify_slopes(&gradients);
+  for (name, raster) in [
+    ("azimuths", &azimuths),
+    ("gradients", &gradients),
+    ("runout_zones", &runout_zones),
+    ("profile_curvatures", &curvatures.profile),
+    ("plan_curvatures", &curvatures.plan),
+    ("total_curvatures", &curvatures.total),
+    ("slope_classes", &slope_classes),
+    ("ates_ratings", &ates_ratings),
+  ] {
+    let path: PathBuf = output_dir.join(format!("{}.tif", name));
+    fs::write(&path, serialize_to_geotiff_with_options(raster, geotiff_options)?)?;
+    eprintln!("Wrote {}", path.display());
+  }
+  Ok(())
+}
+
+fn hillshade(
+  dem: &Path,
+  output: &Path,
+  options: &HillshadeOptions,
+  geotiff_options: &GeoTiffOptions,
+) -> Result<(), Box<dyn Error>> {
+  let elevations: Raster<f64> = read_dem(dem)?;
+  fs::write(output, serialize_to_geotiff_with_options(&compute_hillshade(&elevations, options), geotiff_options)?)?;
+  eprintln!("Wrote {}", output.display());
+  Ok(())
+}
+
+fn fill(dem: &Path, output: &Path, epsilon: Option<f64>, geotiff_options: &GeoTiffOptions) -> Result<(), Box<dyn Error>> {
+  let elevations: Raster<f64> = read_dem(dem)?;
+  fs::write(output, serialize_to_geotiff_with_options(&fill_depressions(&elevations, epsilon), geotiff_options)?)?;
+  eprintln!("Wrote {}", output.display());
+  Ok(())
+}
+
+fn streams(
+  dem: &Path,
+  output_dir: &Path,
+  threshold_area: f64,
+  routing: FlowRouting,
+  geotiff_options: &GeoTiffOptions,
+) -> Result<(), Box<dyn Error>> {
+  let elevations: Raster<f64> = read_dem(dem)?;
+  fs::create_dir_all(output_dir)?;
+  let network: StreamNetwork = extract_stream_network_with_routing(&elevations, threshold_area, routing);
+  for (name, raster) in [("flow_accumulation", &network.flow_accumulation), ("stream_orders", &network.stream_orders)] {
+    let path: PathBuf = output_dir.join(format!("{}.tif", name));
+    fs::write(&path, serialize_to_geotiff_with_options(raster, geotiff_options)?)?;
+    eprintln!("Wrote {}", path.display());
+  }
+  let path: PathBuf = output_dir.join("streams.geojson");
+  fs::write(&path, streams_to_geojson(&network))?;
+  eprintln!("Wrote {}", path.display());
+  Ok(())
+}
+
+fn watershed(
+  dem: &Path,
+  pour_point: Coordinate,
+  snap_distance: Option<f64>,
+  output_dir: &Path,
+  geotiff_options: &GeoTiffOptions,
+) -> Result<(), Box<dyn Error>> {
+  let elevations: Raster<f64> = read_dem(dem)?;
+  fs::create_dir_all(output_dir)?;
+  let watershed: Watershed = delineate_watershed(&elevations, pour_point, snap_distance)?;
+  let path: PathBuf = output_dir.join("watershed.tif");
+  fs::write(&path, serialize_to_geotiff_with_options(&watershed.mask, geotiff_options)?)?;
+  eprintln!("Wrote {}", path.display());
+  let path: PathBuf = output_dir.join("watershed.geojson");
+  fs::write(&path, watershed_to_geojson(&watershed))?;
+  eprintln!("Wrote {} ({:.3} km²)", path.display(), watershed.area / 1e6);
+  Ok(())
+}
+
+fn route(
+  dem: &Path,
+  start: Coordinate,
+  end: Coordinate,
+  options: PathOptions,
+  analysis_options: AnalysisOptions,
+  output: Option<&Path>,
+  format: Option<RouteFormat>,
+) -> Result<(), Box<dyn Error>> {
+  let format: RouteFormat = format.unwrap_or_else(|| {
+    match output.and_then(|path| path.extension()).and_then(|ext| ext.to_str()) {
+      Some(ext) if ext.eq_ignore_ascii_case("gpx") => RouteFormat::Gpx,
+      _ => RouteFormat::Geojson,
+    }
+  });
+
+  let terrain = TerrainStack::with_options(read_dem(dem)?, &options.excluded_aspects, analysis_options);
+  let path = terrain.find_path(start, end, &options, None)?;
+  eprintln!("Found path with {} points", path.len());
+  let exposures: Vec<OverheadExposure> = if options.report_overhead_exposure {
+    terrain.overhead_exposure(&path, &options.excluded_aspects)
+  } else {
+    vec![]
+  };
+  let exposed: usize = exposures.iter().filter(|exposure| exposure.start_zone_area > 0.0).count();
+  if exposed > 0 {
+    eprintln!("{} points lie below start zones on the excluded aspects", exposed);
+  }
+
+  let contents: String = match format {
+    RouteFormat::Geojson => path_to_geojson_with_exposure(&path, &exposures),
+    RouteFormat::Gpx => path_to_gpx(&path),
+  };
+  match output {
+    Some(path) => fs::write(path, contents)?,
+    None => println!("{}", contents),
+  }
+  Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+  match cli.command {
+    Command::Analyze {
+      dem,
+      output_dir,
+      excluded_aspects,
+      algorithm,
+      alpha_angle,
+      runout_config,
+      compression,
+      tile_size,
+      overviews,
+      stack,
+    } => {
+      let analysis_options = AnalysisOptions {
+        algorithm,
+        runout_model: alpha_angle.unwrap_or_default(),
+        runout_config: read_runout_config(runout_config.as_deref())?,
+      };
+      let geotiff_options = GeoTiffOptions { compression: compression.into(), predictor: true, tile_size, overviews };
+      analyze(&dem, &output_dir, &excluded_aspects, analysis_options, &geotiff_options, stack)
+    }
+    Command::Hillshade { dem, output, azimuth, altitude, z_factor, multidirectional, algorithm, compression } => {
+      let options = HillshadeOptions { azimuth, altitude, z_factor, multidirectional, algorithm };
+      let geotiff_options = GeoTiffOptions { compression: compression.into(), predictor: true, ..Default::default() };
+      hillshade(&dem, &output, &options, &geotiff_options)
+    }
+    Command::Streams { dem, output_dir, threshold_area, routing, compression } => {
+      let geotiff_options = GeoTiffOptions { compression: compression.into(), predictor: true, ..Default::default() };
+      streams(&dem, &output_dir, threshold_area, routing, &geotiff_options)
+    }
+    Command::Fill { dem, output, epsilon, compression } => {
+      let geotiff_options = GeoTiffOptions { compression: compression.into(), predictor: true, ..Default::default() };
+      fill(&dem, &output, epsilon, &geotiff_options)
+    }
+    Command::Watershed { dem, pour_point, snap_distance, output_dir, compression } => {
+      let geotiff_options = GeoTiffOptions { compression: compression.into(), predictor: true, ..Default::default() };
+      watershed(&dem, pour_point, snap_distance, &output_dir, &geotiff_options)
+    }
+    Command::Route {
+      dem,
+      start,
+      end,
+      max_gradient,
+      excluded_aspects,
+      aspect_gradient_threshold,
+      max_ates_rating,
+      stream_barrier_order,
+      stream_crossing_cost,
+      stream_threshold_area,
+      overhead_exposure_cost,
+      report_overhead_exposure,
+      algorithm,
+      alpha_angle,
+      runout_config,
+      avoid_runout_zones,
+      output,
+      format,
+    } => {
+      let defaults = PathOptions::default();
+      let options = PathOptions {
+        max_gradient: max_gradient.unwrap_or(defaults.max_gradient),
+        excluded_aspects,
+        aspect_gradient_threshold: aspect_gradient_threshold.unwrap_or(defaults.aspect_gradient_threshold),
+        max_ates_rating,
+        avoid_runout_zones,
+        stream_barrier_order,
+        stream_crossing_cost: stream_crossing_cost.unwrap_or(defaults.stream_crossing_cost),
+        stream_threshold_area: stream_threshold_area.unwrap_or(defaults.stream_threshold_area),
+        overhead_exposure_cost: overhead_exposure_cost.unwrap_or(defaults.overhead_exposure_cost),
+        report_overhead_exposure,
+        ..defaults
+      };
+      let analysis_options = AnalysisOptions {
+        algorithm,
+        runout_model: alpha_angle.unwrap_or_default(),
+        runout_config: read_runout_config(runout_config.as_deref())?,
+      };
+      route(&dem, start, end, options, analysis_options, output.as_deref(), format)
+    }
+  }
+}
+
+fn main() -> ExitCode {
+  match run(Cli::parse()) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("Error: {}", e);
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,226 @@
+use serde::Serialize;
+
+use crate::{
+  azimuth::{start_zones, Aspect},
+  find_path::PathPoint,
+  raster::Raster,
+  runout::{RunoutConfig, RunoutModel, RunoutTracer},
+};
+
+/// Avalanche start zones hanging above one cell
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct OverheadExposure {
+  /// Ground area in m² of the start zones whose debris can reach the cell
+  pub start_zone_area: f64,
+  /// Steepest gradient (rise/run) among those start zones
+  pub max_gradient: f64,
+  /// Height in metres of the highest of those start zones above the cell
+  pub relief: f64,
+}
+
+/// Start zones hanging above every cell of a terrain, traced once so routing and path reports only look them up.
+/// Each start zone's debris reaches the cells its own runout would, traced with the same model, stop rules and
+/// spreading as `compute_runout_zones`.
+pub struct OverheadHazard {
+  exposure: Raster<OverheadExposure>,
+}
+
+impl OverheadHazard {
+  /// Trace the start zones on the `excluded_aspects`, as `compute_runout_zones` finds them, one by one to every
+  /// cell their debris can reach. Start zones and voids have no exposure.
+  pub fn new(
+    elevations: &Raster<f64>,
+    azimuths: &Raster<f64>,
+    gradients: &Raster<f64>,
+    excluded_aspects: &[Aspect],
+    model: RunoutModel,
+    config: &RunoutConfig,
+  ) -> Self {
+    let width: usize = elevations.width();
+    let start_zones: Vec<bool> = start_zones(azimuths, gradients, excluded_aspects, config);
+    let sources: Vec<usize> = (0..start_zones.len()).filter(|&index| start_zones[index]).collect();
+    let mut tracer = RunoutTracer::new(elevations, gradients, start_zones, model, config);
+
+    let mut exposure: Raster<OverheadExposure> = elevations.like(OverheadExposure::default());
+    for source in sources {
+      let area: f64 = elevations.pixel_spacing(source / width).iter().product();
+      tracer.trace(&[source]);
+      for (index, _) in tracer.reached() {
+        // A D8 trace blends its own start zone into runout
+        if tracer.is_start_zone(index) {
+          continue;
+        }
+        let cell: &mut OverheadExposure = &mut exposure.data_mut()[index];
+        cell.start_zone_area += area;
+        cell.max_gradient = cell.max_gradient.max(gradients.data()[source]);
+        cell.relief = cell.relief.max(elevations.data()[source] - elevations.data()[index]);
+      }
+    }
+    Self { exposure }
+  }
+
+  /// Exposure of every cell, on the grid of the elevations it was traced on
+  pub fn exposure(&self) -> &Raster<OverheadExposure> {
+    &self.exposure
+  }
+
+  /// Exposure of the cell at (x, y)
+  pub fn exposure_at(&self, x: usize, y: usize) -> OverheadExposure {
+    self.exposure[(x, y)]
+  }
+
+  /// Exposure at each point of a routed `path`, or none for points outside the raster
+  pub fn along(&self, path: &[PathPoint]) -> Vec<OverheadExposure> {
+    path
+      .iter()
+      .map(|point| match self.exposure.coord_to_pixel(point.coordinate) {
+        Some((x, y)) => self.exposure_at(x, y),
+        None => OverheadExposure::default(),
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    azimuth::{analyze_slopes, AnalysisOptions, SlopeAnalysis},
+    hydrology::FlowRouting,
+    raster::{CrsInfo, GeoTransform},
+  };
+  use georaster::Coordinate;
+
+  /// Column of 30 m pixels facing south: 5 rows of start zone falling 0.6 per metre, then 15 rows of runout
+  /// falling 0.05 per metre. Returns the elevations, azimuths and gradients.
+  fn south_face() -> (Raster<f64>, Raster<f64>, Raster<f64>) {
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    let gradient = |y: usize| -> f64 { if y < 5 { 0.6 } else { 0.05 } };
+    let data: Vec<f64> = (0..20).map(|y| 1000.0 - (0..y).map(|above| 30.0 * gradient(above)).sum::<f64>()).collect();
+    let elevations = Raster::new(data, 1, 20, transform, CrsInfo::default());
+    let azimuths: Raster<f64> = elevations.like(180.0);
+    let gradients: Raster<f64> = elevations.with_data((0..20).map(gradient).collect());
+    (elevations, azimuths, gradients)
+  }
+
+  #[test]
+  fn d8_exposure_counts_start_zones_within_the_runout_length() {
+    let (elevations, azimuths, gradients) = south_face();
+    // Spreading would also carry debris on down the column
+    let config = RunoutConfig { max_runout_cells: 3, spread_iterations: 0, ..Default::default() };
+    let hazard =
+      OverheadHazard::new(&elevations, &azimuths, &gradients, &[Aspect::South], RunoutModel::D8Trace, &config);
+
+    // Rows 2 to 4 are within three cells of the top of the runout; start zones themselves have no exposure
+    let top_of_runout: OverheadExposure = hazard.exposure_at(0, 5);
+    assert_eq!(top_of_runout.start_zone_area, 3.0 * 900.0);
+    assert_eq!(top_of_runout.max_gradient, 0.6);
+    assert!((top_of_runout.relief - 3.0 * 18.0).abs() < 1e-9);
+    assert_eq!(hazard.exposure_at(0, 6).start_zone_area, 2.0 * 900.0);
+    assert_eq!(hazard.exposure_at(0, 7).start_zone_area, 900.0);
+    assert_eq!(hazard.exposure_at(0, 8), OverheadExposure::default());
+    assert!((0..5).all(|y| hazard.exposure_at(0, y) == OverheadExposure::default()));
+
+    // Other aspects hang nothing above the column
+    let hazard =
+      OverheadHazard::new(&elevations, &azimuths, &gradients, &[Aspect::North], RunoutModel::D8Trace, &config);
+    assert!(hazard.exposure().data().iter().all(|exposure| exposure.start_zone_area == 0.0));
+  }
+
+  #[test]
+  fn d8_exposure_ends_where_the_runout_trace_fades() {
+    // 0.92 decay drops below 0.05 intensity after 36 cells, well short of the 50 cell limit, and spreading then
+    // carries debris two cells further down the column
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    let data: Vec<f64> = (0..60).map(|y| 1000.0 - 3.0 * y as f64).collect();
+    let elevations = Raster::new(data, 1, 60, transform, CrsInfo::default());
+    let azimuths: Raster<f64> = elevations.like(180.0);
+    let gradients: Raster<f64> = elevations.with_data((0..60).map(|y| if y == 0 { 0.6 } else { 0.1 }).collect());
+    let config = RunoutConfig::default();
+    let hazard =
+      OverheadHazard::new(&elevations, &azimuths, &gradients, &[Aspect::South], RunoutModel::D8Trace, &config);
+    assert_eq!(hazard.exposure_at(0, 38).start_zone_area, 900.0);
+    assert_eq!(hazard.exposure_at(0, 39), OverheadExposure::default());
+  }
+
+  /// 31 x 30 grid of 30 m pixels: a south face falling 0.6 per metre for 10 rows into a gully down the middle
+  /// column, a flat floor for 10 rows and a counter-slope rising 0.2 per metre, all sloping gently into the gully
+  fn gully() -> Raster<f64> {
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    let elevation = |x: usize, y: usize| -> f64 {
+      1000.0 - 18.0 * y.min(10) as f64 + 6.0 * y.saturating_sub(20) as f64 + 4.0 * x.abs_diff(15) as f64
+    };
+    let data: Vec<f64> = (0..30 * 31).map(|index| elevation(index % 31, index / 31)).collect();
+    Raster::new(data, 31, 30, transform, CrsInfo::default())
+  }
+
+  #[test]
+  fn exposure_covers_the_runout_zones_of_every_model() {
+    let elevations: Raster<f64> = gully();
+    let configs: [RunoutConfig; 3] = [
+      RunoutConfig::default(),
+      RunoutConfig { fill_depressions: true, ..Default::default() },
+      RunoutConfig { flow_routing: FlowRouting::Mfd, ..Default::default() },
+    ];
+    let options: Vec<AnalysisOptions> = configs
+      .into_iter()
+      .map(|runout_config| AnalysisOptions { runout_config, ..Default::default() })
+      .chain([15.0, 25.0].map(|alpha| AnalysisOptions {
+        runout_model: RunoutModel::AlphaAngle { alpha },
+        ..Default::default()
+      }))
+      .collect();
+    for options in options {
+      let slopes: SlopeAnalysis = analyze_slopes(&elevations, &[Aspect::South], &options);
+      let start_zones: Vec<bool> =
+        start_zones(&slopes.azimuths, &slopes.gradients, &[Aspect::South], &options.runout_config);
+      let hazard = OverheadHazard::new(
+        &elevations,
+        &slopes.azimuths,
+        &slopes.gradients,
+        &[Aspect::South],
+        options.runout_model,
+        &options.runout_config,
+      );
+      assert!(start_zones.iter().any(|&start_zone| start_zone));
+      for (index, exposure) in hazard.exposure().data().iter().enumerate() {
+        let runout: bool = slopes.runout_zones.data()[index] > 0.0 && !start_zones[index];
+        assert_eq!(exposure.start_zone_area > 0.0, runout, "cell {index} with {options:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn alpha_angle_exposure_fans_out_across_the_flat() {
+    let elevations: Raster<f64> = gully();
+    let options = AnalysisOptions { runout_model: RunoutModel::AlphaAngle { alpha: 15.0 }, ..Default::default() };
+    let slopes: SlopeAnalysis = analyze_slopes(&elevations, &[Aspect::South], &options);
+    let hazard = OverheadHazard::new(
+      &elevations,
+      &slopes.azimuths,
+      &slopes.gradients,
+      &[Aspect::South],
+      options.runout_model,
+      &options.runout_config,
+    );
+    // Debris from more than one column of the face reaches the gully floor halfway across the flat, and beyond
+    // onto the counter-slope
+    let floor: OverheadExposure = hazard.exposure_at(15, 15);
+    assert!(floor.start_zone_area > 10.0 * 900.0, "{floor:?}");
+    assert!(floor.relief > 150.0);
+    assert!(hazard.exposure_at(15, 21).start_zone_area > 0.0);
+    assert_eq!(hazard.exposure_at(15, 29), OverheadExposure::default());
+  }
+
+  #[test]
+  fn exposure_along_a_path_looks_up_each_point() {
+    let (elevations, azimuths, gradients) = south_face();
+    let config = RunoutConfig { max_runout_cells: 3, ..Default::default() };
+    let hazard =
+      OverheadHazard::new(&elevations, &azimuths, &gradients, &[Aspect::South], RunoutModel::D8Trace, &config);
+    let point =
+      |coordinate: Coordinate| PathPoint { coordinate, elevation: 0.0, azimuth: 180.0, aspect: Aspect::South };
+    let path: Vec<PathPoint> = vec![point(elevations.pixel_to_coord(0, 5)), point(Coordinate { x: 0.0, y: 0.0 })];
+    assert_eq!(hazard.along(&path), [hazard.exposure_at(0, 5), OverheadExposure::default()]);
+  }
+}
@@ -1,22 +1,741 @@
 use georaster::geotiff::{GeoTiffReader, RasterValue};
-use std::io::{Read, Seek};
-use wasm_bindgen::prelude::*;
+use georaster::Coordinate;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::{Index, IndexMut};
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+use tiff::TiffError;
 
-pub fn get_raster<R: Read + Seek + Send>(geotiff: &mut GeoTiffReader<R>) -> Result<Vec<Vec<f64>>, JsValue> {
+use crate::{
+  error::PathfinderError,
+  gdal::{parse_nodata, GdalMetadata, GDAL_METADATA, GDAL_NODATA},
+};
+
+/// Affine transform from pixel space to model space, in GDAL coefficient order:
+/// `x = origin_x + col * pixel_width + row * row_rotation`
+/// `y = origin_y + col * column_rotation + row * pixel_height`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoTransform {
+  pub origin_x: f64,
+  pub pixel_width: f64,
+  pub row_rotation: f64,
+  pub origin_y: f64,
+  pub column_rotation: f64,
+  pub pixel_height: f64,
+}
+
+impl GeoTransform {
+  /// North-up transform with no rotation terms
+  pub fn new(origin: [f64; 2], pixel_size: [f64; 2]) -> Self {
+    Self {
+      origin_x: origin[0],
+      pixel_width: pixel_size[0],
+      row_rotation: 0.0,
+      origin_y: origin[1],
+      column_rotation: 0.0,
+      pixel_height: pixel_size[1],
+    }
+  }
+
+  pub fn origin(&self) -> [f64; 2] {
+    [self.origin_x, self.origin_y]
+  }
+
+  pub fn pixel_size(&self) -> [f64; 2] {
+    [self.pixel_width, self.pixel_height]
+  }
+
+  /// Model coordinate of a (fractional) pixel position
+  pub fn apply(&self, col: f64, row: f64) -> Coordinate {
+    Coordinate {
+      x: self.origin_x + col * self.pixel_width + row * self.row_rotation,
+      y: self.origin_y + col * self.column_rotation + row * self.pixel_height,
+    }
+  }
+
+  /// Fractional pixel position of a model coordinate, or `None` if the transform is degenerate
+  pub fn invert(&self, coord: Coordinate) -> Option<(f64, f64)> {
+    let det: f64 = self.pixel_width * self.pixel_height - self.row_rotation * self.column_rotation;
+    if det == 0.0 {
+      return None;
+    }
+    let dx: f64 = coord.x - self.origin_x;
+    let dy: f64 = coord.y - self.origin_y;
+    let col: f64 = (dx * self.pixel_height - dy * self.row_rotation) / det;
+    let row: f64 = (dy * self.pixel_width - dx * self.column_rotation) / det;
+    Some((col, row))
+  }
+
+  /// Same grid with its origin moved to the (fractional) pixel position (col, row)
+  pub fn offset(&self, col: f64, row: f64) -> Self {
+    let origin: Coordinate = self.apply(col, row);
+    Self { origin_x: origin.x, origin_y: origin.y, ..*self }
+  }
+}
+
+/// Coordinate reference system information carried over from the source GeoTIFF
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrsInfo {
+  /// GeoKeyDirectoryTag (34735)
+  pub geo_keys: Vec<u32>,
+  /// GeoAsciiParamsTag (34737)
+  pub ascii_params: Option<String>,
+  /// GeoDoubleParamsTag (34736)
+  pub double_params: Option<Vec<f64>>,
+}
+
+/// Whether pixel values describe an area or a point at the grid coordinate (GTRasterTypeGeoKey).
+/// The transform of a PixelIsArea image ties pixel corners, that of a PixelIsPoint image pixel centres.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterType {
+  PixelIsArea,
+  PixelIsPoint,
+}
+
+impl CrsInfo {
+  /// Value of GeoKey `id` when it is stored inline in the GeoKeyDirectory
+  pub fn geo_key(&self, id: u16) -> Option<u32> {
+    let keys: &[u32] = &self.geo_keys;
+    let count: usize = *keys.get(3)? as usize;
+    keys
+      .get(4..4 + count * 4)?
+      .chunks_exact(4)
+      .find(|entry| entry[0] == id as u32 && entry[1] == 0)
+      .map(|entry| entry[3])
+  }
+
+  /// Raster type from GTRasterTypeGeoKey, or `None` if the key is missing or unknown
+  pub fn raster_type(&self) -> Option<RasterType> {
+    match self.geo_key(1025)? {
+      1 => Some(RasterType::PixelIsArea),
+      2 => Some(RasterType::PixelIsPoint),
+      _ => None,
+    }
+  }
+
+  /// True if model coordinates are longitude/latitude degrees (GTModelTypeGeoKey = ModelTypeGeographic)
+  pub fn is_geographic(&self) -> bool {
+    self.geo_key(1024) == Some(2)
+  }
+
+  /// Length in metres of one projected model unit (ProjLinearUnitsGeoKey), assuming metres if unknown
+  pub fn linear_unit_meters(&self) -> f64 {
+    match self.geo_key(3076) {
+      Some(9002) => 0.3048,                // International foot
+      Some(9003) => 1200.0 / 3937.0,       // US survey foot
+      Some(9036) => 1000.0,                // Kilometre
+      _ => 1.0,
+    }
+  }
+}
+
+/// Metres per degree of latitude and of longitude at `latitude` degrees on the WGS84 ellipsoid
+fn meters_per_degree(latitude: f64) -> [f64; 2] {
+  let phi: f64 = latitude.to_radians();
+  let latitude_meters: f64 = 111_132.92 - 559.82 * (2.0 * phi).cos() + 1.175 * (4.0 * phi).cos();
+  let longitude_meters: f64 = 111_412.84 * phi.cos() - 93.5 * (3.0 * phi).cos();
+  [latitude_meters, longitude_meters.max(0.0)]
+}
+
+/// Georeferenced single-band raster stored as a contiguous row-major buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster<T> {
+  data: Vec<T>,
+  width: usize,
+  height: usize,
+  transform: GeoTransform,
+  crs: CrsInfo,
+  nodata: Option<T>,
+}
+
+impl<T: Copy> Raster<T> {
+  pub fn new(data: Vec<T>, width: usize, height: usize, transform: GeoTransform, crs: CrsInfo) -> Self {
+    assert_eq!(data.len(), width * height, "raster buffer does not match {}x{}", width, height);
+    Self { data, width, height, transform, crs, nodata: None }
+  }
+
+  /// New raster on the same grid and georeferencing as `self`, filled with `value`
+  pub fn like<U: Copy>(&self, value: U) -> Raster<U> {
+    Raster {
+      data: vec![value; self.data.len()],
+      width: self.width,
+      height: self.height,
+      transform: self.transform,
+      crs: self.crs.clone(),
+      nodata: None,
+    }
+  }
+
+  /// New raster on the same grid and georeferencing as `self`, holding `data`
+  pub fn with_data<U: Copy>(&self, data: Vec<U>) -> Raster<U> {
+    Raster::new(data, self.width, self.height, self.transform, self.crs.clone())
+  }
+
+  pub fn map<U: Copy>(&self, f: impl Fn(T) -> U) -> Raster<U> {
+    self.with_data(self.data.iter().map(|&v| f(v)).collect())
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  pub fn data(&self) -> &[T] {
+    &self.data
+  }
+
+  pub fn data_mut(&mut self) -> &mut [T] {
+    &mut self.data
+  }
+
+  pub fn into_data(self) -> Vec<T> {
+    self.data
+  }
+
+  pub fn transform(&self) -> &GeoTransform {
+    &self.transform
+  }
+
+  pub fn crs(&self) -> &CrsInfo {
+    &self.crs
+  }
+
+  pub fn nodata(&self) -> Option<T> {
+    self.nodata
+  }
+
+  pub fn set_nodata(&mut self, nodata: Option<T>) {
+    self.nodata = nodata;
+  }
+
+  pub fn get(&self, x: usize, y: usize) -> Option<T> {
+    if x < self.width && y < self.height {
+      Some(self.data[y * self.width + x])
+    } else {
+      None
+    }
+  }
+
+  /// Ground distance in metres between horizontally and vertically adjacent pixels on row `y`.
+  /// Geographic rasters are corrected for the latitude of the row.
+  pub fn pixel_spacing(&self, y: usize) -> [f64; 2] {
+    let t: &GeoTransform = &self.transform;
+    let dx: f64 = t.pixel_width.hypot(t.column_rotation);
+    let dy: f64 = t.row_rotation.hypot(t.pixel_height);
+    if self.crs.is_geographic() {
+      let latitude: f64 = self.center_transform().apply(self.width as f64 / 2.0, y as f64).y;
+      let [latitude_meters, longitude_meters] = meters_per_degree(latitude);
+      [dx * longitude_meters, dy * latitude_meters]
+    } else {
+      let unit: f64 = self.crs.linear_unit_meters();
+      [dx * unit, dy * unit]
+    }
+  }
+
+  /// True if `other` covers exactly the same pixel grid
+  pub fn same_grid<U>(&self, other: &Raster<U>) -> bool {
+    self.width == other.width && self.height == other.height && self.transform == other.transform
+  }
+
+  /// Transform from pixel positions to model coordinates with integer positions at pixel centres.
+  /// PixelIsArea rasters are shifted by half a pixel; PixelIsPoint and untagged rasters are taken to tie their
+  /// transform to pixel centres already.
+  pub fn center_transform(&self) -> GeoTransform {
+    match self.crs.raster_type() {
+      Some(RasterType::PixelIsArea) => self.transform.offset(0.5, 0.5),
+      Some(RasterType::PixelIsPoint) | None => self.transform,
+    }
+  }
+
+  /// Model coordinate of the centre of the pixel at column `x`, row `y`
+  pub fn pixel_to_coord(&self, x: usize, y: usize) -> Coordinate {
+    self.center_transform().apply(x as f64, y as f64)
+  }
+
+  /// Pixel containing `coord`, or `None` if it falls outside the raster
+  pub fn coord_to_pixel(&self, coord: Coordinate) -> Option<(usize, usize)> {
+    let (col, row) = self.center_transform().invert(coord)?;
+    let (col, row) = (col.round(), row.round());
+    if col < 0.0 || row < 0.0 || col >= self.width as f64 || row >= self.height as f64 {
+      return None;
+    }
+    Some((col as usize, row as usize))
+  }
+}
+
+impl Raster<f64> {
+  /// True if `value` is this raster's nodata value (NaN nodata matches any NaN)
+  pub fn is_nodata_value(&self, value: f64) -> bool {
+    match self.nodata {
+      Some(nodata) if nodata.is_nan() => value.is_nan(),
+      Some(nodata) => value == nodata,
+      None => false,
+    }
+  }
+
+  pub fn is_nodata(&self, x: usize, y: usize) -> bool {
+    self.is_nodata_value(self[(x, y)])
+  }
+}
+
+/// Indexed by `(x, y)`, i.e. `(column, row)`
+impl<T> Index<(usize, usize)> for Raster<T> {
+  type Output = T;
+
+  fn index(&self, (x, y): (usize, usize)) -> &T {
+    &self.data[y * self.width + x]
+  }
+}
+
+impl<T> IndexMut<(usize, usize)> for Raster<T> {
+  fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+    &mut self.data[y * self.width + x]
+  }
+}
+
+/// Open a GeoTIFF and read its first band as elevation-like values, applying any GDAL scale/offset.
+/// Cells equal to the GDAL_NODATA value are left unscaled and become the raster's nodata.
+pub fn read_geotiff<R: Read + Seek + Send>(mut reader: R) -> Result<Raster<f64>, PathfinderError> {
+  let tags: ExtraTags = read_extra_tags(&mut reader)?;
+  reader
+    .seek(SeekFrom::Start(0))
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+
+  let mut geotiff: GeoTiffReader<R> = GeoTiffReader::open(reader)
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+  // georaster selects the last IFD, which is the smallest overview in tiled/COG files
+  geotiff
+    .seek_to_image(0)
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+  let mut raster: Raster<f64> = get_raster(&mut geotiff)?;
+  raster.set_nodata(tags.nodata);
+  raster.crs.double_params = tags.double_params;
+  if let Some(transform) = tags.transform {
+    raster.transform = transform;
+  }
+
+  apply_scale_offset(&mut raster, tags.metadata.scale_offset(0));
+  Ok(raster)
+}
+
+/// Open a GeoTIFF and read all of its bands, named by their GDAL band descriptions (`band_<n>` if unnamed).
+/// Scale/offset and nodata are handled per band as in `read_geotiff`. Multi-band images must hold Float32 samples.
+pub fn read_geotiff_bands<R: Read + Seek + Send>(mut reader: R) -> Result<Vec<(String, Raster<f64>)>, PathfinderError> {
+  let tags: ExtraTags = read_extra_tags(&mut reader)?;
+  reader
+    .seek(SeekFrom::Start(0))
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+  if tags.samples <= 1 {
+    return Ok(vec![(band_name(&tags.metadata, 0), read_geotiff(reader)?)]);
+  }
+
+  // tiff and georaster can't decode multi-sample gray images, so the chunks are decoded here
+  let transform: GeoTransform = tags.transform.ok_or(PathfinderError::MissingGeoreference)?;
+  let layout: BandLayout = read_band_layout(&mut reader)?;
+  let crs = CrsInfo { double_params: tags.double_params.clone(), ..layout.crs.clone() };
+  Ok(
+    decode_bands(&mut reader, &layout)?
+      .into_iter()
+      .enumerate()
+      .map(|(band, data)| {
+        let mut raster: Raster<f64> = Raster::new(data, layout.width, layout.height, transform, crs.clone());
+        raster.set_nodata(tags.nodata);
+        apply_scale_offset(&mut raster, tags.metadata.scale_offset(band));
+        (band_name(&tags.metadata, band), raster)
+      })
+      .collect(),
+  )
+}
+
+fn band_name(metadata: &GdalMetadata, band: usize) -> String {
+  metadata.band_description(band).map_or_else(|| format!("band_{}", band + 1), str::to_string)
+}
+
+/// Apply `value * scale + offset` to every cell that isn't nodata
+fn apply_scale_offset(raster: &mut Raster<f64>, (scale, offset): (f64, f64)) {
+  if scale != 1.0 || offset != 0.0 {
+    for i in 0..raster.data().len() {
+      let value: f64 = raster.data()[i];
+      if !raster.is_nodata_value(value) {
+        raster.data_mut()[i] = value * scale + offset;
+      }
+    }
+  }
+}
+
+/// Tags of the first image that georaster doesn't expose
+struct ExtraTags {
+  metadata: GdalMetadata,
+  samples: usize,
+  nodata: Option<f64>,
+  double_params: Option<Vec<f64>>,
+  /// Full affine transform; georaster assumes the tie point is pixel (0, 0) and drops rotation terms
+  transform: Option<GeoTransform>,
+}
+
+fn read_extra_tags<R: Read + Seek>(reader: &mut R) -> Result<ExtraTags, PathfinderError> {
+  let mut decoder: Decoder<&mut R> = Decoder::new(reader)
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+  let metadata: GdalMetadata = decoder
+    .get_tag_ascii_string(GDAL_METADATA)
+    .map(|xml| GdalMetadata::parse(&xml))
+    .unwrap_or_default();
+  let samples: usize = decoder.find_tag_unsigned(Tag::SamplesPerPixel).ok().flatten().unwrap_or(1);
+  let nodata: Option<f64> = decoder
+    .get_tag_ascii_string(GDAL_NODATA)
+    .ok()
+    .and_then(|value| parse_nodata(&value));
+  let double_params: Option<Vec<f64>> = decoder.get_tag_f64_vec(Tag::GeoDoubleParamsTag).ok();
+
+  let model_transformation: Option<Vec<f64>> = decoder.get_tag_f64_vec(Tag::ModelTransformationTag).ok();
+  let tie_points: Option<Vec<f64>> = decoder.get_tag_f64_vec(Tag::ModelTiepointTag).ok();
+  let pixel_scale: Option<Vec<f64>> = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).ok();
+  let transform: Option<GeoTransform> = match (model_transformation, tie_points, pixel_scale) {
+    (Some(t), _, _) if t.len() >= 8 => Some(GeoTransform {
+      origin_x: t[3],
+      pixel_width: t[0],
+      row_rotation: t[1],
+      origin_y: t[7],
+      column_rotation: t[4],
+      pixel_height: t[5],
+    }),
+    // Tie raster point (I, J) to model point (X, Y)
+    (_, Some(tie), Some(scale)) if tie.len() >= 6 && scale.len() >= 2 => Some(GeoTransform::new(
+      [tie[3] - tie[0] * scale[0], tie[4] + tie[1] * scale[1]],
+      [scale[0], -scale[1]],
+    )),
+    _ => None,
+  };
+
+  Ok(ExtraTags { metadata, samples, nodata, double_params, transform })
+}
+
+/// Chunk layout of a multi-band Float32 image
+struct BandLayout {
+  width: usize,
+  height: usize,
+  samples: usize,
+  /// Band-interleaved (PlanarConfiguration=2) rather than pixel-interleaved
+  planar: bool,
+  tiled: bool,
+  chunk_width: usize,
+  chunk_height: usize,
+  offsets: Vec<u64>,
+  byte_counts: Vec<u64>,
+  compression: u16,
+  predictor: u16,
+  little_endian: bool,
+  crs: CrsInfo,
+}
+
+fn read_band_layout<R: Read + Seek>(reader: &mut R) -> Result<BandLayout, PathfinderError> {
+  let invalid = |e: TiffError| PathfinderError::InvalidGeoTiff { message: e.to_string() };
+  let mut byte_order: [u8; 2] = [0; 2];
+  reader
+    .read_exact(&mut byte_order)
+    .and_then(|_| reader.seek(SeekFrom::Start(0)))
+    .map_err(|e| PathfinderError::InvalidGeoTiff { message: e.to_string() })?;
+
+  let mut decoder: Decoder<&mut R> = Decoder::new(reader).map_err(invalid)?;
+  let (width, height) = decoder.dimensions().map_err(|_| PathfinderError::MissingDimensions)?;
+  let (width, height) = (width as usize, height as usize);
+  let samples: usize = decoder.get_tag_u32(Tag::SamplesPerPixel).map_err(invalid)? as usize;
+  let bits: Vec<u16> = decoder.get_tag_u16_vec(Tag::BitsPerSample).map_err(invalid)?;
+  let formats: Vec<u16> = decoder.find_tag_unsigned_vec(Tag::SampleFormat).map_err(invalid)?.unwrap_or_default();
+  if bits.iter().any(|&b| b != 32) || formats.is_empty() || formats.iter().any(|&f| f != 3) {
+    return Err(PathfinderError::UnsupportedSampleFormat {
+      found: format!("{} bands with bits per sample {:?} and sample formats {:?}", samples, bits, formats),
+    });
+  }
+
+  let unsigned = |decoder: &mut Decoder<&mut R>, tag: Tag| -> Result<Option<u32>, PathfinderError> {
+    decoder.find_tag_unsigned(tag).map_err(invalid)
+  };
+  let planar: bool = unsigned(&mut decoder, Tag::PlanarConfiguration)? == Some(2);
+  let compression: u16 = unsigned(&mut decoder, Tag::Compression)?.unwrap_or(1) as u16;
+  let predictor: u16 = unsigned(&mut decoder, Tag::Predictor)?.unwrap_or(1) as u16;
+  let (tiled, chunk_width, chunk_height, offsets_tag, byte_counts_tag) =
+    match unsigned(&mut decoder, Tag::TileWidth)? {
+      Some(tile_width) => {
+        let tile_length: u32 = decoder.get_tag_u32(Tag::TileLength).map_err(invalid)?;
+        (true, tile_width as usize, tile_length as usize, Tag::TileOffsets, Tag::TileByteCounts)
+      }
+      None => {
+        let rows_per_strip: usize = unsigned(&mut decoder, Tag::RowsPerStrip)?.map_or(height, |rows| rows as usize);
+        (false, width, rows_per_strip.clamp(1, height.max(1)), Tag::StripOffsets, Tag::StripByteCounts)
+      }
+    };
+  let offsets: Vec<u64> = decoder.get_tag_u64_vec(offsets_tag).map_err(invalid)?;
+  let byte_counts: Vec<u64> = decoder.get_tag_u64_vec(byte_counts_tag).map_err(invalid)?;
+  let crs = CrsInfo {
+    geo_keys: decoder.get_tag_u32_vec(Tag::GeoKeyDirectoryTag).unwrap_or_default(),
+    ascii_params: decoder.get_tag_ascii_string(Tag::GeoAsciiParamsTag).ok(),
+    double_params: None,
+  };
+
+  Ok(BandLayout {
+    width,
+    height,
+    samples,
+    planar,
+    tiled,
+    chunk_width,
+    chunk_height,
+    offsets,
+    byte_counts,
+    compression,
+    predictor,
+    little_endian: &byte_order == b"II",
+    crs,
+  })
+}
+
+/// Decode every strip or tile of a multi-band image into one buffer per band
+fn decode_bands<R: Read + Seek>(reader: &mut R, layout: &BandLayout) -> Result<Vec<Vec<f64>>, PathfinderError> {
+  let (width, height) = (layout.width, layout.height);
+  let chunk_samples: usize = if layout.planar { 1 } else { layout.samples };
+  let across: usize = width.div_ceil(layout.chunk_width);
+  let per_plane: usize = across * height.div_ceil(layout.chunk_height);
+  let chunks: usize = if layout.planar { per_plane * layout.samples } else { per_plane };
+  if layout.offsets.len() < chunks || layout.byte_counts.len() < chunks {
+    return Err(PathfinderError::InvalidGeoTiff {
+      message: format!("Expected {} chunks, found {}", chunks, layout.offsets.len().min(layout.byte_counts.len())),
+    });
+  }
+
+  let mut bands: Vec<Vec<f64>> = vec![vec![0.0; width * height]; layout.samples];
+  for chunk in 0..chunks {
+    let (plane, index) = (chunk / per_plane, chunk % per_plane);
+    let x0: usize = (index % across) * layout.chunk_width;
+    let y0: usize = (index / across) * layout.chunk_height;
+    // Tiles are always full size; the last strip may stop at the bottom of the image
+    let rows: usize = if layout.tiled { layout.chunk_height } else { layout.chunk_height.min(height - y0) };
+    let row_len: usize = layout.chunk_width * chunk_samples;
+    let values: Vec<f32> = read_chunk(reader, layout, chunk, rows, row_len, chunk_samples)?;
+
+    for row in 0..rows.min(height - y0) {
+      for col in 0..layout.chunk_width.min(width - x0) {
+        for sample in 0..chunk_samples {
+          bands[plane + sample][(y0 + row) * width + x0 + col] = values[row * row_len + col * chunk_samples + sample] as f64;
+        }
+      }
+    }
+  }
+  Ok(bands)
+}
+
+/// Read, decompress and undo the predictor of one strip or tile holding `rows` rows of `row_len` samples
+fn read_chunk<R: Read + Seek>(
+  reader: &mut R,
+  layout: &BandLayout,
+  chunk: usize,
+  rows: usize,
+  row_len: usize,
+  stride: usize,
+) -> Result<Vec<f32>, PathfinderError> {
+  let io_error = |e: std::io::Error| PathfinderError::InvalidGeoTiff { message: e.to_string() };
+  let mut compressed: Vec<u8> = vec![0; layout.byte_counts[chunk] as usize];
+  reader.seek(SeekFrom::Start(layout.offsets[chunk])).map_err(io_error)?;
+  reader.read_exact(&mut compressed).map_err(io_error)?;
+
+  let mut bytes: Vec<u8> = match layout.compression {
+    1 => compressed,
+    5 => weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+      .decode(&compressed)
+      .map_err(|e| PathfinderError::InvalidGeoTiff { message: format!("Failed to decode LZW chunk: {}", e) })?,
+    8 | 32946 => {
+      let mut bytes: Vec<u8> = Vec::new();
+      flate2::read::ZlibDecoder::new(&compressed[..]).read_to_end(&mut bytes).map_err(io_error)?;
+      bytes
+    }
+    other => return Err(PathfinderError::InvalidGeoTiff { message: format!("Unsupported compression {}", other) }),
+  };
+  if bytes.len() < rows * row_len * 4 {
+    return Err(PathfinderError::InvalidGeoTiff { message: format!("Chunk {} is truncated", chunk) });
+  }
+  bytes.truncate(rows * row_len * 4);
+
+  match layout.predictor {
+    1 if layout.little_endian => {
+      Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+    1 => Ok(bytes.chunks_exact(4).map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]])).collect()),
+    // Floating-point predictor: each row is differenced big-endian byte planes
+    3 => {
+      let mut values: Vec<f32> = Vec::with_capacity(rows * row_len);
+      for row in bytes.chunks_exact_mut(row_len * 4) {
+        for i in stride..row.len() {
+          row[i] = row[i].wrapping_add(row[i - stride]);
+        }
+        for i in 0..row_len {
+          values.push(f32::from_be_bytes([row[i], row[row_len + i], row[2 * row_len + i], row[3 * row_len + i]]));
+        }
+      }
+      Ok(values)
+    }
+    other => Err(PathfinderError::InvalidGeoTiff {
+      message: format!("Unsupported predictor {} for floating-point samples", other),
+    }),
+  }
+}
+
+/// Convert a raw sample of any integer or float type to `f64`
+fn sample_to_f64(value: &RasterValue) -> Option<f64> {
+  match *value {
+    RasterValue::U8(v) => Some(v as f64),
+    RasterValue::U16(v) => Some(v as f64),
+    RasterValue::U32(v) => Some(v as f64),
+    RasterValue::U64(v) => Some(v as f64),
+    RasterValue::I8(v) => Some(v as f64),
+    RasterValue::I16(v) => Some(v as f64),
+    RasterValue::I32(v) => Some(v as f64),
+    RasterValue::I64(v) => Some(v as f64),
+    RasterValue::F32(v) => Some(v as f64),
+    RasterValue::F64(v) => Some(v),
+    _ => None,
+  }
+}
+
+/// Read the raw samples of the selected band, without applying scale/offset
+pub fn get_raster<R: Read + Seek + Send>(geotiff: &mut GeoTiffReader<R>) -> Result<Raster<f64>, PathfinderError> {
   let (width, height) = geotiff.image_info().dimensions
-    .ok_or_else(|| JsValue::from_str("Failed to get image dimensions"))?;
+    .ok_or(PathfinderError::MissingDimensions)?;
   let width: usize = width as usize;
   let height: usize = height as usize;
 
-  let mut raster_data: Vec<Vec<f64>> = vec![vec![0.0; width]; height];
+  let origin: [f64; 2] = geotiff.origin()
+    .ok_or(PathfinderError::MissingGeoreference)?;
+  let pixel_size: [f64; 2] = geotiff.pixel_size()
+    .ok_or(PathfinderError::MissingGeoreference)?;
+  let crs = CrsInfo {
+    geo_keys: geotiff.geo_keys.clone().unwrap_or_default(),
+    ascii_params: geotiff.geo_params.clone(),
+    double_params: None,
+  };
+
+  let mut raster_data: Vec<f64> = vec![0.0; width * height];
   for pixel in geotiff.pixels(0, 0, width as u32, height as u32) {
     let (x, y, value) = pixel;
     let data: f64 = match value {
-      RasterValue::F64(v) => v,
-      RasterValue::F32(v) => v as f64,
-      _ => return Err(JsValue::from_str(&format!("Data must be f64, found: {:?}", value))),
+      // georaster reports undecodable chunks as NoData
+      RasterValue::NoData => {
+        return Err(PathfinderError::InvalidGeoTiff { message: format!("Failed to decode pixel ({}, {})", x, y) })
+      }
+      _ => sample_to_f64(&value)
+        .ok_or_else(|| PathfinderError::UnsupportedSampleFormat { found: format!("{:?}", value) })?,
     };
-    raster_data[y as usize][x as usize] = data;
+    raster_data[y as usize * width + x as usize] = data;
+  }
+  Ok(Raster::new(raster_data, width, height, GeoTransform::new(origin, pixel_size), crs))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use tiff::encoder::{colortype, TiffEncoder};
+
+  /// 4x3 grid of 10 m pixels whose transform origin is (1000, 2000), tagged with `raster_type` if given
+  fn grid(raster_type: Option<u32>) -> Raster<f64> {
+    let geo_keys: Vec<u32> = match raster_type {
+      Some(value) => vec![1, 1, 0, 1, 1025, 0, 1, value],
+      None => vec![],
+    };
+    let crs: CrsInfo = CrsInfo { geo_keys, ..Default::default() };
+    Raster::new(vec![0.0; 12], 4, 3, GeoTransform::new([1000.0, 2000.0], [10.0, -10.0]), crs)
+  }
+
+  #[test]
+  fn pixel_is_area_coordinates_are_pixel_centres() {
+    let raster: Raster<f64> = grid(Some(1));
+    assert_eq!(raster.crs().raster_type(), Some(RasterType::PixelIsArea));
+    assert_eq!(raster.pixel_to_coord(0, 0), Coordinate { x: 1005.0, y: 1995.0 });
+    assert_eq!(raster.pixel_to_coord(3, 2), Coordinate { x: 1035.0, y: 1975.0 });
+    // Anywhere inside a pixel maps back to it, and the outer corner is just off the raster
+    assert_eq!(raster.coord_to_pixel(Coordinate { x: 1000.1, y: 1999.9 }), Some((0, 0)));
+    assert_eq!(raster.coord_to_pixel(Coordinate { x: 1039.9, y: 1970.1 }), Some((3, 2)));
+    assert_eq!(raster.coord_to_pixel(Coordinate { x: 999.9, y: 1995.0 }), None);
+  }
+
+  #[test]
+  fn pixel_is_point_and_untagged_coordinates_sit_on_the_grid() {
+    for raster in [grid(Some(2)), grid(None)] {
+      assert_eq!(raster.pixel_to_coord(0, 0), Coordinate { x: 1000.0, y: 2000.0 });
+      assert_eq!(raster.coord_to_pixel(Coordinate { x: 1034.9, y: 1980.1 }), Some((3, 2)));
+      assert_eq!(raster.coord_to_pixel(Coordinate { x: 995.1, y: 2000.0 }), Some((0, 0)));
+    }
+  }
+
+  /// 3x2 Int16 GeoTIFF of 10 m pixels with the given GDAL_METADATA and GDAL_NODATA tags
+  fn int16_geotiff(samples: &[i16], metadata: Option<&str>, nodata: Option<&str>) -> Vec<u8> {
+    let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut cursor).unwrap();
+    let mut image = encoder.new_image::<colortype::GrayI16>(3, 2).unwrap();
+    let directory = image.encoder();
+    directory.write_tag(Tag::ModelPixelScaleTag, &[10.0, 10.0, 0.0][..]).unwrap();
+    directory.write_tag(Tag::ModelTiepointTag, &[0.0, 0.0, 0.0, 1000.0, 2000.0, 0.0][..]).unwrap();
+    directory.write_tag(Tag::GeoKeyDirectoryTag, &[1u16, 1, 0, 1, 1025, 0, 1, 1][..]).unwrap();
+    if let Some(metadata) = metadata {
+      directory.write_tag(GDAL_METADATA, metadata).unwrap();
+    }
+    if let Some(nodata) = nodata {
+      directory.write_tag(GDAL_NODATA, nodata).unwrap();
+    }
+    image.write_data(samples).unwrap();
+    cursor.into_inner()
+  }
+
+  #[test]
+  fn integer_samples_are_read_as_is_without_scale_or_offset() {
+    let bytes: Vec<u8> = int16_geotiff(&[-3, 0, 7, 1200, -32768, 32767], None, None);
+    let raster: Raster<f64> = read_geotiff(Cursor::new(bytes)).unwrap();
+    assert_eq!(raster.data(), [-3.0, 0.0, 7.0, 1200.0, -32768.0, 32767.0]);
+    assert_eq!((raster.width(), raster.height()), (3, 2));
+    assert_eq!(raster.transform(), &GeoTransform::new([1000.0, 2000.0], [10.0, -10.0]));
+    assert_eq!(raster.nodata(), None);
+  }
+
+  #[test]
+  fn gdal_scale_and_offset_apply_to_every_cell_but_nodata() {
+    let metadata: &str = "<GDALMetadata>\n  <Item name=\"SCALE\" sample=\"0\" role=\"scale\">0.5</Item>\n  \
+                          <Item name=\"OFFSET\" sample=\"0\" role=\"offset\">1000</Item>\n</GDALMetadata>\n";
+    let bytes: Vec<u8> = int16_geotiff(&[0, 2, -4, 100, -9999, 7], Some(metadata), Some("-9999"));
+    let raster: Raster<f64> = read_geotiff(Cursor::new(bytes)).unwrap();
+    assert_eq!(raster.data(), [1000.0, 1001.0, 998.0, 1050.0, -9999.0, 1003.5]);
+    assert_eq!(raster.nodata(), Some(-9999.0));
+    assert!(raster.is_nodata(1, 1));
+  }
+
+  #[test]
+  fn geographic_pixel_spacing_shrinks_east_west_with_latitude() {
+    // 1 arc-second pixels, one row on the equator and one at 60° north
+    let crs = CrsInfo { geo_keys: vec![1, 1, 0, 1, 1024, 0, 1, 2], ..Default::default() };
+    let arc_second: f64 = 1.0 / 3600.0;
+    let transform = GeoTransform::new([10.0, 60.0], [arc_second, -arc_second]);
+    let north: Raster<f64> = Raster::new(vec![0.0; 4], 2, 2, transform, crs.clone());
+    let transform = GeoTransform::new([10.0, 0.0], [arc_second, -arc_second]);
+    let equator: Raster<f64> = Raster::new(vec![0.0; 4], 2, 2, transform, crs);
+    assert!(north.crs().is_geographic());
+
+    let [equator_x, equator_y] = equator.pixel_spacing(0);
+    assert!((equator_x - 30.92).abs() < 0.01 && (equator_y - 30.72).abs() < 0.01, "{equator_x}, {equator_y}");
+    let [north_x, north_y] = north.pixel_spacing(0);
+    assert!((north_x - 15.5).abs() < 0.05 && (north_y - 30.9).abs() < 0.05, "{north_x}, {north_y}");
+  }
+
+  #[test]
+  fn projected_pixel_spacing_converts_feet_to_metres() {
+    let transform = GeoTransform::new([1_000_000.0, 500_000.0], [10.0, -20.0]);
+    let feet =
+      |unit: u32| CrsInfo { geo_keys: vec![1, 1, 0, 2, 1024, 0, 1, 1, 3076, 0, 1, unit], ..Default::default() };
+    let international: Raster<f64> = Raster::new(vec![0.0; 4], 2, 2, transform, feet(9002));
+    assert_eq!(international.pixel_spacing(1), [3.048, 6.096]);
+    let survey: Raster<f64> = Raster::new(vec![0.0; 4], 2, 2, transform, feet(9003));
+    assert!((survey.pixel_spacing(1)[0] - 10.0 * 1200.0 / 3937.0).abs() < 1e-12);
+    // Metres when the unit is missing
+    assert_eq!(grid(None).pixel_spacing(0), [10.0, 10.0]);
   }
-  Ok(raster_data)
 }
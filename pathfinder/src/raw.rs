@@ -0,0 +1,217 @@
+//! Raw raster buffers: a small little-endian header followed by Float32 samples.
+//! Cheaper to produce and consume than a GeoTIFF when handing rasters between wasm calls.
+//!
+//! | offset | type        | field                                                         |
+//! |--------|-------------|---------------------------------------------------------------|
+//! | 0      | `[u8; 4]`   | magic `PFRR`                                                  |
+//! | 4      | `u16`       | version (1)                                                   |
+//! | 6      | `u16`       | flags: 1 = nodata, 2 = GeoAsciiParams, 4 = GeoDoubleParams     |
+//! | 8      | `u32`       | byte offset of the samples, a multiple of 4                   |
+//! | 12     | `u32`       | width                                                         |
+//! | 16     | `u32`       | height                                                        |
+//! | 20     | `[f64; 6]`  | transform, in `GeoTransform` field order                      |
+//! | 68     | `f64`       | nodata                                                        |
+//! | 76     | `u32`, ...  | GeoKey count, then the GeoKeyDirectory                        |
+//! |        | `u32`, ...  | byte length, then the UTF-8 GeoAsciiParams                    |
+//! |        | `u32`, ...  | count, then the GeoDoubleParams                               |
+//!
+//! The samples are row-major Float32, so JS can view them in place with
+//! `new Float32Array(buffer.buffer, buffer.byteOffset + offset, width * height)`.
+
+use crate::{
+  error::PathfinderError,
+  raster::{CrsInfo, GeoTransform, Raster},
+};
+
+pub const RAW_RASTER_MAGIC: &[u8; 4] = b"PFRR";
+const VERSION: u16 = 1;
+const HAS_NODATA: u16 = 1;
+const HAS_ASCII_PARAMS: u16 = 2;
+const HAS_DOUBLE_PARAMS: u16 = 4;
+
+/// True if `bytes` start like a raw raster buffer rather than a TIFF
+pub fn is_raw_raster(bytes: &[u8]) -> bool {
+  bytes.starts_with(RAW_RASTER_MAGIC)
+}
+
+/// Encode `raster` as a raw raster buffer, with samples narrowed to Float32 as in `serialize_to_geotiff`
+pub fn serialize_to_raw(raster: &Raster<f64>) -> Vec<u8> {
+  let crs: &CrsInfo = raster.crs();
+  let mut flags: u16 = 0;
+  if raster.nodata().is_some() {
+    flags |= HAS_NODATA;
+  }
+  if crs.ascii_params.is_some() {
+    flags |= HAS_ASCII_PARAMS;
+  }
+  if crs.double_params.is_some() {
+    flags |= HAS_DOUBLE_PARAMS;
+  }
+
+  let mut header: Vec<u8> = Vec::with_capacity(128);
+  header.extend_from_slice(RAW_RASTER_MAGIC);
+  header.extend_from_slice(&VERSION.to_le_bytes());
+  header.extend_from_slice(&flags.to_le_bytes());
+  header.extend_from_slice(&0u32.to_le_bytes()); // data offset, patched below
+  header.extend_from_slice(&(raster.width() as u32).to_le_bytes());
+  header.extend_from_slice(&(raster.height() as u32).to_le_bytes());
+  let transform: &GeoTransform = raster.transform();
+  for value in [
+    transform.origin_x,
+    transform.pixel_width,
+    transform.row_rotation,
+    transform.origin_y,
+    transform.column_rotation,
+    transform.pixel_height,
+    raster.nodata().unwrap_or(0.0),
+  ] {
+    header.extend_from_slice(&value.to_le_bytes());
+  }
+  header.extend_from_slice(&(crs.geo_keys.len() as u32).to_le_bytes());
+  for key in &crs.geo_keys {
+    header.extend_from_slice(&key.to_le_bytes());
+  }
+  let ascii_params: &[u8] = crs.ascii_params.as_deref().unwrap_or_default().as_bytes();
+  header.extend_from_slice(&(ascii_params.len() as u32).to_le_bytes());
+  header.extend_from_slice(ascii_params);
+  let double_params: &[f64] = crs.double_params.as_deref().unwrap_or_default();
+  header.extend_from_slice(&(double_params.len() as u32).to_le_bytes());
+  for param in double_params {
+    header.extend_from_slice(&param.to_le_bytes());
+  }
+  header.resize(header.len().next_multiple_of(4), 0);
+  let data_offset: u32 = header.len() as u32;
+  header[8..12].copy_from_slice(&data_offset.to_le_bytes());
+
+  let mut bytes: Vec<u8> = header;
+  bytes.reserve_exact(raster.data().len() * 4);
+  for &value in raster.data() {
+    bytes.extend_from_slice(&(value as f32).to_le_bytes());
+  }
+  bytes
+}
+
+/// Cursor over the header fields
+struct HeaderReader<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], PathfinderError> {
+    let end: usize = self.position.checked_add(len).filter(|&end| end <= self.bytes.len()).ok_or_else(|| {
+      PathfinderError::InvalidRawRaster { message: "Header is truncated".to_string() }
+    })?;
+    let field: &'a [u8] = &self.bytes[self.position..end];
+    self.position = end;
+    Ok(field)
+  }
+
+  fn u16(&mut self) -> Result<u16, PathfinderError> {
+    Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+  }
+
+  fn u32(&mut self) -> Result<u32, PathfinderError> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn f64(&mut self) -> Result<f64, PathfinderError> {
+    Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+  }
+}
+
+/// Decode a buffer written by `serialize_to_raw`
+pub fn read_raw(bytes: &[u8]) -> Result<Raster<f64>, PathfinderError> {
+  let mut header = HeaderReader { bytes, position: 0 };
+  if header.take(4)? != RAW_RASTER_MAGIC {
+    return Err(PathfinderError::InvalidRawRaster { message: "Missing PFRR magic".to_string() });
+  }
+  let version: u16 = header.u16()?;
+  if version != VERSION {
+    return Err(PathfinderError::InvalidRawRaster { message: format!("Unsupported version {}", version) });
+  }
+  let flags: u16 = header.u16()?;
+  let data_offset: usize = header.u32()? as usize;
+  let width: usize = header.u32()? as usize;
+  let height: usize = header.u32()? as usize;
+  let transform = GeoTransform {
+    origin_x: header.f64()?,
+    pixel_width: header.f64()?,
+    row_rotation: header.f64()?,
+    origin_y: header.f64()?,
+    column_rotation: header.f64()?,
+    pixel_height: header.f64()?,
+  };
+  let nodata: f64 = header.f64()?;
+
+  let geo_key_count: usize = header.u32()? as usize;
+  let geo_keys: Vec<u32> = (0..geo_key_count).map(|_| header.u32()).collect::<Result<_, _>>()?;
+  let ascii_len: usize = header.u32()? as usize;
+  let ascii_params: String = String::from_utf8(header.take(ascii_len)?.to_vec())
+    .map_err(|e| PathfinderError::InvalidRawRaster { message: e.to_string() })?;
+  let double_count: usize = header.u32()? as usize;
+  let double_params: Vec<f64> = (0..double_count).map(|_| header.f64()).collect::<Result<_, _>>()?;
+  let crs = CrsInfo {
+    geo_keys,
+    ascii_params: (flags & HAS_ASCII_PARAMS != 0).then_some(ascii_params),
+    double_params: (flags & HAS_DOUBLE_PARAMS != 0).then_some(double_params),
+  };
+
+  let samples: &[u8] = width
+    .checked_mul(height)
+    .and_then(|count| count.checked_mul(4))
+    .and_then(|len| bytes.get(data_offset..data_offset.checked_add(len)?))
+    .filter(|_| data_offset >= header.position)
+    .ok_or_else(|| PathfinderError::InvalidRawRaster {
+      message: format!("Expected {}x{} samples at byte {}", width, height, data_offset),
+    })?;
+  let data: Vec<f64> = samples
+    .chunks_exact(4)
+    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+    .collect();
+
+  let mut raster: Raster<f64> = Raster::new(data, width, height, transform, crs);
+  raster.set_nodata((flags & HAS_NODATA != 0).then_some(nodata));
+  Ok(raster)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 3x2 grid of 30 m pixels with a void, tagged PixelIsArea in UTM zone 11N
+  fn grid() -> Raster<f64> {
+    let crs = CrsInfo {
+      geo_keys: vec![1, 1, 0, 2, 1025, 0, 1, 1, 3072, 0, 1, 32611],
+      ascii_params: Some("WGS 84 / UTM zone 11N|".to_string()),
+      double_params: Some(vec![6378137.0, 298.257223563]),
+    };
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    let mut raster = Raster::new(vec![1200.5, 1210.25, -9999.0, 1190.0, 1185.75, 1170.125], 3, 2, transform, crs);
+    raster.set_nodata(Some(-9999.0));
+    raster
+  }
+
+  #[test]
+  fn raw_round_trip_keeps_samples_and_georeferencing() {
+    let bytes: Vec<u8> = serialize_to_raw(&grid());
+    assert!(is_raw_raster(&bytes));
+    assert_eq!(read_raw(&bytes).unwrap(), grid());
+  }
+
+  #[test]
+  fn raw_samples_are_aligned_float32() {
+    let bytes: Vec<u8> = serialize_to_raw(&grid());
+    let offset: usize = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    assert_eq!(offset % 4, 0);
+    assert_eq!(bytes.len(), offset + 6 * 4);
+    assert_eq!(f32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()), 1210.25);
+  }
+
+  #[test]
+  fn truncated_raw_buffer_is_rejected() {
+    let bytes: Vec<u8> = serialize_to_raw(&grid());
+    assert!(matches!(read_raw(&bytes[..bytes.len() - 1]), Err(PathfinderError::InvalidRawRaster { .. })));
+    assert!(matches!(read_raw(b"II*\0"), Err(PathfinderError::InvalidRawRaster { .. })));
+  }
+}
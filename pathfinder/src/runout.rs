@@ -0,0 +1,503 @@
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+  error::PathfinderError,
+  hydrology::{
+    compute_d8_flow_directions, compute_flow_partitions, direction, downstream, fill_depressions, highest_first,
+    neighbors, FlowRouting, PriorityCell, FILL_EPSILON,
+  },
+  raster::Raster,
+};
+
+/// Default reach angle in degrees; roughly one standard deviation below the alpha angles observed for
+/// typical 25–35° tracks, so runout is estimated conservatively
+pub const DEFAULT_ALPHA_ANGLE: f64 = 20.0;
+
+/// Faintest runout intensity passed on, below which a path ends as a D8 trace does
+const MIN_INTENSITY: f64 = 0.05;
+
+/// Share of the steepest drop a downhill neighbour needs for debris to spread into it, which limits
+/// lateral spreading to roughly a 90° fan about the fall line
+const SPREAD_RATIO: f64 = 0.5;
+
+/// How debris is routed from start zones to derive runout zones
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RunoutModel {
+  /// Trace the D8 flow path from each start zone for a fixed number of cells with a fixed per-cell decay, then
+  /// spread laterally, as set by `RunoutConfig`
+  #[default]
+  D8Trace,
+  /// Debris reaches a cell while the energy line falling from the start zone at `alpha` degrees stays above
+  /// the terrain, so runout length grows with vertical drop
+  AlphaAngle { alpha: f64 },
+}
+
+impl RunoutModel {
+  /// Alpha-angle model reaching `alpha` degrees below the horizontal from the start zone
+  pub fn alpha_angle(alpha: f64) -> Result<RunoutModel, PathfinderError> {
+    if alpha > 0.0 && alpha < 90.0 {
+      Ok(RunoutModel::AlphaAngle { alpha })
+    } else {
+      Err(PathfinderError::InvalidAlphaAngle { alpha })
+    }
+  }
+}
+
+/// Start zone and D8 runout parameters, for tuning the avalanche model to a region or snowpack.
+/// Omitted fields deserialize to the defaults given below.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunoutConfig {
+  /// Minimum rise/run of a start zone cell; tan(10°) by default, where red aspect shading stops
+  pub start_zone_gradient: f64,
+  /// Rise/run below which start zone cells also get faint runout, fading in from this gradient down to
+  /// `start_zone_gradient` to blend red aspect shading into amber runout; 0.35 (about 19°) by default
+  pub blend_gradient: f64,
+  /// Degrees either side of an excluded aspect's range within which a cell still counts as facing it; 22.5°
+  /// by default
+  pub aspect_tolerance: f64,
+  /// Most cells a D8 runout path is traced below its start zone; 50 by default
+  pub max_runout_cells: usize,
+  /// Intensity kept per D8 cell travelled; 0.92 by default
+  pub decay_rate: f64,
+  /// Lateral spreading passes filling gaps between D8 paths; 2 by default
+  pub spread_iterations: usize,
+  /// Intensity kept per lateral spreading step; 0.7 by default
+  pub spread_decay: f64,
+  /// Fill DEM pits before flow routing, so noise doesn't end runout paths early; off by default
+  pub fill_depressions: bool,
+  /// How runout is routed downslope; the default D8 is followed by lateral spreading, while `d-infinity` and
+  /// `mfd` disperse by themselves
+  pub flow_routing: FlowRouting,
+}
+
+impl Default for RunoutConfig {
+  fn default() -> Self {
+    Self {
+      start_zone_gradient: 0.176,
+      blend_gradient: 0.35,
+      aspect_tolerance: 22.5,
+      max_runout_cells: 50,
+      decay_rate: 0.92,
+      spread_iterations: 2,
+      spread_decay: 0.7,
+      fill_depressions: false,
+      flow_routing: FlowRouting::D8,
+    }
+  }
+}
+
+/// Where debris goes from each cell, with the scratch state one model keeps while tracing
+enum Routing {
+  /// Follow D8 flow directions, then spread laterally
+  D8 { flow_directions: Raster<u8> },
+  /// Pass intensity along flow partitions, visiting cells by their rank in highest-first order, with the cells
+  /// travelled to each one
+  Dispersive { partitions: Vec<[f64; 8]>, rank: Vec<usize>, travelled: Vec<usize> },
+  /// Fan out below an energy line losing `friction` per metre, with the highest energy line reaching each cell and
+  /// the elevation of the start zone it fell from
+  AlphaAngle { friction: f64, energy: Vec<f64>, source_elevation: Vec<f64> },
+}
+
+/// Runout intensities of the cells a trace has reached so far
+struct Reach {
+  intensity: Vec<f64>,
+  cells: Vec<usize>,
+}
+
+impl Reach {
+  /// Raise the intensity at `index` to `value`, returning true if it was lower
+  fn raise(&mut self, index: usize, value: f64) -> bool {
+    if value <= self.intensity[index] {
+      return false;
+    }
+    if self.intensity[index] == 0.0 {
+      self.cells.push(index);
+    }
+    self.intensity[index] = value;
+    true
+  }
+
+  /// Forget every cell reached
+  fn clear(&mut self) {
+    for &index in &self.cells {
+      self.intensity[index] = 0.0;
+    }
+    self.cells.clear();
+  }
+}
+
+/// Runout traced with one model from any set of start zone cells: all of them at once for the runout layer, or
+/// each on its own to find the start zones hanging above a cell. Only the cells a trace reaches are touched, so
+/// tracing start zones one by one costs no more memory than tracing them together.
+pub(crate) struct RunoutTracer<'a> {
+  elevations: &'a Raster<f64>,
+  gradients: &'a Raster<f64>,
+  start_zones: Vec<bool>,
+  config: RunoutConfig,
+  routing: Routing,
+  reach: Reach,
+}
+
+impl<'a> RunoutTracer<'a> {
+  /// Tracer for `model` tuned by `config`, where `start_zones` flags the start zone cells by index. The D8 trace
+  /// runs with `config.flow_routing`, on a filled surface if `config.fill_depressions` is set.
+  pub(crate) fn new(
+    elevations: &'a Raster<f64>,
+    gradients: &'a Raster<f64>,
+    start_zones: Vec<bool>,
+    model: RunoutModel,
+    config: &RunoutConfig,
+  ) -> Self {
+    let cells: usize = elevations.width() * elevations.height();
+    let routing: Routing = match model {
+      RunoutModel::AlphaAngle { alpha } => Routing::AlphaAngle {
+        friction: alpha.to_radians().tan(),
+        energy: vec![f64::NEG_INFINITY; cells],
+        source_elevation: vec![0.0; cells],
+      },
+      RunoutModel::D8Trace => {
+        let filled: Raster<f64>;
+        let surface: &Raster<f64> = if config.fill_depressions {
+          filled = fill_depressions(elevations, Some(FILL_EPSILON));
+          &filled
+        } else {
+          elevations
+        };
+        match config.flow_routing {
+          FlowRouting::D8 => Routing::D8 { flow_directions: compute_d8_flow_directions(surface) },
+          routing => {
+            let mut rank: Vec<usize> = vec![0; cells];
+            for (position, index) in highest_first(surface).into_iter().enumerate() {
+              rank[index] = position;
+            }
+            let partitions: Vec<[f64; 8]> = compute_flow_partitions(surface, routing);
+            Routing::Dispersive { partitions, rank, travelled: vec![0; cells] }
+          }
+        }
+      }
+    };
+    let reach = Reach { intensity: vec![0.0; cells], cells: Vec::new() };
+    Self { elevations, gradients, start_zones, config: *config, routing, reach }
+  }
+
+  /// True if the cell at `index` is a start zone
+  pub(crate) fn is_start_zone(&self, index: usize) -> bool {
+    self.start_zones[index]
+  }
+
+  /// Trace runout from the start zone cells at `sources`, replacing the previous trace
+  pub(crate) fn trace(&mut self, sources: &[usize]) {
+    match &mut self.routing {
+      Routing::D8 { flow_directions } => {
+        self.reach.clear();
+        let (start_zones, config): (&[bool], &RunoutConfig) = (&self.start_zones, &self.config);
+        follow_flow_directions(flow_directions, self.gradients, start_zones, config, sources, &mut self.reach);
+        spread_laterally(self.elevations, &self.start_zones, &self.config, &mut self.reach);
+      }
+      Routing::Dispersive { partitions, rank, travelled } => {
+        for &index in &self.reach.cells {
+          travelled[index] = 0;
+        }
+        self.reach.clear();
+        pass_along_partitions(self.elevations, partitions, rank, travelled, &self.config, sources, &mut self.reach);
+      }
+      Routing::AlphaAngle { friction, energy, source_elevation } => {
+        for &index in &self.reach.cells {
+          energy[index] = f64::NEG_INFINITY;
+        }
+        self.reach.clear();
+        follow_energy_line(self.elevations, *friction, energy, source_elevation, sources, &mut self.reach);
+      }
+    }
+  }
+
+  /// Cells the last trace reached, with their runout intensity. Start zones only appear where a D8 trace blends
+  /// them into runout.
+  pub(crate) fn reached(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+    let blends: bool = matches!(self.routing, Routing::D8 { .. });
+    self
+      .reach
+      .cells
+      .iter()
+      .filter(move |&&index| blends || !self.start_zones[index])
+      .map(|&index| (index, self.reach.intensity[index]))
+  }
+}
+
+/// D8 runout: start zone cells just steeper than `start_zone_gradient` get faint runout of their own, fading out
+/// towards `blend_gradient` to blend red aspect shading into amber runout. Debris then follows the flow directions
+/// from each source at full intensity, keeping `decay_rate` of it per cell, and marks every cell it crosses but
+/// the start zones. A path ends in a sink, off the raster, after `max_runout_cells` cells or once its intensity
+/// drops below 0.05.
+fn follow_flow_directions(
+  flow_directions: &Raster<u8>,
+  gradients: &Raster<f64>,
+  start_zones: &[bool],
+  config: &RunoutConfig,
+  sources: &[usize],
+  reach: &mut Reach,
+) {
+  let width: usize = flow_directions.width();
+  let blend_range: f64 = config.blend_gradient - config.start_zone_gradient;
+  for &source in sources {
+    // Up to 50% intensity at the start zone gradient itself
+    let above_threshold: f64 = gradients.data()[source] - config.start_zone_gradient;
+    if above_threshold < blend_range {
+      reach.raise(source, (1.0 - above_threshold / blend_range) * 0.5);
+    }
+
+    let mut current: usize = source;
+    let mut intensity: f64 = 1.0;
+    let mut cells: usize = 0;
+    // Runout carries on across flats until it fades out
+    while let Some(next) = downstream(flow_directions, current % width, current / width) {
+      current = next;
+      cells += 1;
+      intensity *= config.decay_rate;
+      if !start_zones[current] {
+        reach.raise(current, intensity);
+      }
+      if cells >= config.max_runout_cells || intensity < MIN_INTENSITY {
+        break;
+      }
+    }
+  }
+}
+
+/// Lateral spreading filling gaps between D8 paths, as debris spreads out flowing downhill: each of
+/// `spread_iterations` passes spreads every cell reached to its 4-connected neighbours, keeping `spread_decay` of
+/// its intensity. Only the cells raised by the previous pass can raise others, and nothing spreads into voids or
+/// start zones.
+fn spread_laterally(elevations: &Raster<f64>, start_zones: &[bool], config: &RunoutConfig, reach: &mut Reach) {
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  let mut raised: Vec<usize> = reach.cells.clone();
+  for _ in 0..config.spread_iterations {
+    // Every spread of a pass starts from the intensities before it
+    let mut spreads: Vec<(usize, f64)> = Vec::new();
+    for &index in &raised {
+      let (x, y) = (index % width, index / width);
+      let spread_intensity: f64 = reach.intensity[index] * config.spread_decay;
+      // A wrapped coordinate is off the raster like one past the end
+      for (nx, ny) in [(x, y.wrapping_sub(1)), (x, y + 1), (x.wrapping_sub(1), y), (x + 1, y)] {
+        if nx < width && ny < height && !elevations.is_nodata(nx, ny) && !start_zones[ny * width + nx] {
+          spreads.push((ny * width + nx, spread_intensity));
+        }
+      }
+    }
+    raised.clear();
+    for (index, spread_intensity) in spreads {
+      if reach.raise(index, spread_intensity) {
+        raised.push(index);
+      }
+    }
+  }
+}
+
+/// Dispersive runout along flow `partitions` that may split among several neighbours, as from D-infinity or MFD
+/// routing. Debris leaves each source at full intensity and keeps `decay_rate` of it per cell, scaled by the share
+/// passed to each neighbour relative to the cell's largest share, so the main flow path fades like a D8 trace
+/// while the flow fanning out to the sides fades faster. Paths end after `max_runout_cells` cells or once the
+/// intensity drops below 0.05.
+fn pass_along_partitions(
+  elevations: &Raster<f64>,
+  partitions: &[[f64; 8]],
+  rank: &[usize],
+  travelled: &mut [usize],
+  config: &RunoutConfig,
+  sources: &[usize],
+  reach: &mut Reach,
+) {
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  // Cells to pass on from, highest first so every cell has received all its inflow before passing it on; flow
+  // only goes downhill, so every cell is queued before its turn comes
+  let mut front: BinaryHeap<Reverse<PriorityCell>> = BinaryHeap::new();
+  for &source in sources {
+    if reach.raise(source, 1.0) {
+      front.push(Reverse(PriorityCell { priority: rank[source] as f64, index: source }));
+    }
+  }
+
+  while let Some(Reverse(PriorityCell { index, .. })) = front.pop() {
+    if travelled[index] >= config.max_runout_cells {
+      continue;
+    }
+    let largest_share: f64 = partitions[index].iter().copied().fold(0.0, f64::max);
+    for (neighbor, (dx, dy)) in neighbors(width, height, index % width, index / width) {
+      let share: f64 = partitions[index][direction(dx, dy)];
+      if share == 0.0 {
+        continue;
+      }
+      let passed: f64 = reach.intensity[index] * config.decay_rate * share / largest_share;
+      let queued: bool = reach.intensity[neighbor] > 0.0;
+      if passed >= MIN_INTENSITY && reach.raise(neighbor, passed) {
+        travelled[neighbor] = travelled[index] + 1;
+        if !queued {
+          front.push(Reverse(PriorityCell { priority: rank[neighbor] as f64, index: neighbor }));
+        }
+      }
+    }
+  }
+}
+
+/// Alpha-angle (energy line) runout. Each source launches an energy line at its own elevation that loses
+/// `friction` per metre of travel. Debris moves downhill within a fan about the fall line, across flats and up
+/// counter-slopes where there is no way down, while the energy line stays above the terrain. A reached cell's
+/// intensity is the share of its drop from the source still above it as energy height: 1 just below the start
+/// zone, 0 at the limit of reach. The sources themselves are left at 0.
+fn follow_energy_line(
+  elevations: &Raster<f64>,
+  friction: f64,
+  energy: &mut [f64],
+  source_elevation: &mut [f64],
+  sources: &[usize],
+  reach: &mut Reach,
+) {
+  let width: usize = elevations.width();
+  let height: usize = elevations.height();
+  // Cells to spread from, highest energy line first
+  let mut front: BinaryHeap<PriorityCell> = BinaryHeap::new();
+  let mut crossed: Vec<usize> = Vec::new();
+  for &source in sources {
+    energy[source] = elevations.data()[source];
+    source_elevation[source] = elevations.data()[source];
+    front.push(PriorityCell { priority: energy[source], index: source });
+    crossed.push(source);
+  }
+
+  while let Some(PriorityCell { priority: cell_energy, index }) = front.pop() {
+    if cell_energy < energy[index] {
+      continue;
+    }
+    let (x, y) = (index % width, index / width);
+    let z: f64 = elevations[(x, y)];
+    let [spacing_x, spacing_y] = elevations.pixel_spacing(y);
+
+    // (neighbour index, travel distance, drop per metre) of every valid neighbour
+    let candidates: Vec<(usize, f64, f64)> = neighbors(width, height, x, y)
+      .filter(|&(neighbor, _)| !elevations.is_nodata_value(elevations.data()[neighbor]))
+      .map(|(neighbor, (dx, dy))| {
+        let distance: f64 = (dx as f64 * spacing_x).hypot(dy as f64 * spacing_y);
+        (neighbor, distance, (z - elevations.data()[neighbor]) / distance)
+      })
+      .collect();
+    let steepest: f64 = candidates.iter().map(|&(_, _, drop)| drop).fold(0.0, f64::max);
+
+    for (neighbor, distance, drop) in candidates {
+      // Follow the fall-line fan downhill; with no way down, go wherever the energy line allows
+      if steepest > 0.0 && drop < SPREAD_RATIO * steepest {
+        continue;
+      }
+      let neighbor_energy: f64 = cell_energy - distance * friction;
+      if neighbor_energy > elevations.data()[neighbor] && neighbor_energy > energy[neighbor] {
+        if energy[neighbor] == f64::NEG_INFINITY {
+          crossed.push(neighbor);
+        }
+        energy[neighbor] = neighbor_energy;
+        source_elevation[neighbor] = source_elevation[index];
+        front.push(PriorityCell { priority: neighbor_energy, index: neighbor });
+      }
+    }
+  }
+
+  for index in crossed {
+    let z: f64 = elevations.data()[index];
+    // A source has no drop of its own to share
+    if z < source_elevation[index] {
+      reach.raise(index, ((energy[index] - z) / (source_elevation[index] - z)).clamp(0.0, 1.0));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+
+  /// Column of 30 m pixels falling 0.6 per metre for 10 rows below the start zone in row 0, then running flat
+  /// for 20 rows
+  fn slope_onto_flat() -> Raster<f64> {
+    let data: Vec<f64> = (0..30).map(|y| 1000.0 - 18.0 * y.min(10) as f64).collect();
+    Raster::new(data, 1, 30, GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]), CrsInfo::default())
+  }
+
+  fn alpha_runout(alpha: f64) -> Raster<f64> {
+    let elevations: Raster<f64> = slope_onto_flat();
+    let gradients: Raster<f64> = elevations.like(0.6);
+    let start_zones: Vec<bool> = (0..30).map(|y| y == 0).collect();
+    let model = RunoutModel::AlphaAngle { alpha };
+    let mut tracer = RunoutTracer::new(&elevations, &gradients, start_zones, model, &RunoutConfig::default());
+    tracer.trace(&[0]);
+    let mut runout: Raster<f64> = elevations.like(0.0);
+    for (index, intensity) in tracer.reached() {
+      runout.data_mut()[index] = intensity;
+    }
+    runout
+  }
+
+  #[test]
+  fn alpha_angle_runout_stops_where_the_energy_line_meets_the_flat() {
+    // tan(16.7°) = 0.3 leaves the energy line 90 m up at the foot of the slope, reaching 300 m across the flat
+    let runout: Raster<f64> = alpha_runout(0.3f64.atan().to_degrees());
+    assert_eq!(runout[(0, 0)], 0.0, "start zones are left untouched");
+    // The energy line keeps half the drop above the uniform slope, then closes in across the flat
+    for y in 1..=10 {
+      assert!((runout[(0, y)] - 0.5).abs() < 1e-9, "row {y} has {}", runout[(0, y)]);
+    }
+    for y in 10..19 {
+      assert!(runout[(0, y)] > runout[(0, y + 1)], "intensity fades across the flat at row {y}");
+    }
+    assert!(runout[(0, 18)] > 0.0);
+    assert!(runout.data()[21..].iter().all(|&intensity| intensity == 0.0));
+
+    // The intensity is the share of the drop from the start zone still above the cell as energy height
+    let expected: f64 = (180.0 - 0.3 * 450.0) / 180.0;
+    assert!((runout[(0, 15)] - expected).abs() < 1e-9);
+  }
+
+  #[test]
+  fn steeper_alpha_angle_reaches_less_far() {
+    // tan(25°) ≈ 0.466 leaves the energy line 40 m up at the foot of the slope, about 86 m of flat
+    let runout: Raster<f64> = alpha_runout(25.0);
+    assert!(runout[(0, 12)] > 0.0);
+    assert!(runout.data()[14..].iter().all(|&intensity| intensity == 0.0));
+  }
+
+  #[test]
+  fn each_trace_starts_afresh() {
+    let elevations: Raster<f64> = slope_onto_flat();
+    let gradients: Raster<f64> = elevations.like(0.6);
+    let start_zones: Vec<bool> = (0..30).map(|y| y < 5).collect();
+    let models: [(RunoutModel, FlowRouting); 3] = [
+      (RunoutModel::D8Trace, FlowRouting::D8),
+      (RunoutModel::D8Trace, FlowRouting::DInfinity),
+      (RunoutModel::AlphaAngle { alpha: 20.0 }, FlowRouting::D8),
+    ];
+    for (model, flow_routing) in models {
+      let config = RunoutConfig { flow_routing, ..Default::default() };
+      let mut tracer = RunoutTracer::new(&elevations, &gradients, start_zones.clone(), model, &config);
+      tracer.trace(&[0]);
+      let from_top: Vec<(usize, f64)> = tracer.reached().collect();
+      tracer.trace(&[4]);
+      let from_bottom: Vec<(usize, f64)> = tracer.reached().collect();
+      tracer.trace(&[0]);
+      assert_eq!(tracer.reached().collect::<Vec<_>>(), from_top, "{model:?} with {flow_routing:?}");
+
+      let mut fresh = RunoutTracer::new(&elevations, &gradients, start_zones.clone(), model, &config);
+      fresh.trace(&[4]);
+      assert_eq!(fresh.reached().collect::<Vec<_>>(), from_bottom, "{model:?} with {flow_routing:?}");
+      assert!(from_top.iter().all(|&(index, intensity)| index >= 5 && intensity > 0.0));
+    }
+  }
+
+  #[test]
+  fn alpha_angle_must_lie_between_flat_and_vertical() {
+    assert_eq!(RunoutModel::alpha_angle(20.0).unwrap(), RunoutModel::AlphaAngle { alpha: 20.0 });
+    for alpha in [0.0, 90.0, -5.0, f64::NAN] {
+      assert!(matches!(RunoutModel::alpha_angle(alpha), Err(PathfinderError::InvalidAlphaAngle { .. })));
+    }
+  }
+}
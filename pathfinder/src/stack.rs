@@ -0,0 +1,313 @@
+use georaster::Coordinate;
+use serde::Serialize;
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  io::{Read, Seek},
+  rc::Rc,
+};
+
+use crate::{
+  azimuth::{analyze_terrain_with_options, compute_runout_zones, AnalysisOptions, Aspect, TerrainAnalysis},
+  classify::{rate_terrain, AtesRating, SlopeClass},
+  curvature::{compute_curvatures, Curvatures},
+  error::PathfinderError,
+  find_path::{find_path, ExplorationCallback, PathOptions, PathPoint, RouteLayers},
+  geotiff::{serialize_bands_to_geotiff, GeoTiffOptions},
+  hydrology::extract_stream_network,
+  overhead::{OverheadExposure, OverheadHazard},
+  raster::{read_geotiff_bands, Raster},
+};
+
+const ELEVATIONS: &str = "elevations";
+const AZIMUTHS: &str = "azimuths";
+const GRADIENTS: &str = "gradients";
+const RUNOUT_ZONES: &str = "runout_zones";
+const PROFILE_CURVATURES: &str = "profile_curvatures";
+const PLAN_CURVATURES: &str = "plan_curvatures";
+const TOTAL_CURVATURES: &str = "total_curvatures";
+const ATES_RATINGS: &str = "ates_ratings";
+
+/// Every layer's value at one pixel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TerrainSample {
+  pub elevation: f64,
+  pub azimuth: f64,
+  pub aspect: Aspect,
+  pub gradient: f64,
+  pub runout: f64,
+  pub profile_curvature: f64,
+  pub plan_curvature: f64,
+  pub total_curvature: f64,
+  pub slope_class: SlopeClass,
+  pub ates_rating: Option<AtesRating>,
+}
+
+/// An overhead hazard with the excluded aspects and analysis options it was traced for
+type TracedHazard = (Vec<Aspect>, AnalysisOptions, Rc<OverheadHazard>);
+
+/// An elevation raster and its derived layers, stored as one multi-band GeoTIFF whose bands are named
+/// `elevations`, `azimuths`, `gradients`, `runout_zones`, `profile_curvatures`, `plan_curvatures`,
+/// `total_curvatures` and `ates_ratings`
+pub struct TerrainStack {
+  pub elevations: Raster<f64>,
+  pub analysis: TerrainAnalysis,
+  /// Used by `reanalyze`; stacks read from a GeoTIFF assume the defaults
+  pub options: AnalysisOptions,
+  /// Stream orders extracted from the elevations, by the bits of their threshold area
+  stream_orders: RefCell<HashMap<u64, Rc<Raster<f64>>>>,
+  /// Overhead hazard last traced
+  overhead_hazard: RefCell<Option<TracedHazard>>,
+}
+
+impl TerrainStack {
+  /// Analyze `elevations` with the default options, deriving runout zones below the `excluded_aspects`
+  pub fn new(elevations: Raster<f64>, excluded_aspects: &[Aspect]) -> Self {
+    Self::with_options(elevations, excluded_aspects, AnalysisOptions::default())
+  }
+
+  /// Analyze `elevations` with `options`, deriving runout zones below the `excluded_aspects`
+  pub fn with_options(elevations: Raster<f64>, excluded_aspects: &[Aspect], options: AnalysisOptions) -> Self {
+    let analysis: TerrainAnalysis = analyze_terrain_with_options(&elevations, excluded_aspects, &options);
+    Self::from_analysis(elevations, analysis, options)
+  }
+
+  /// Stack of layers already derived from `elevations` with `options`
+  pub fn from_analysis(elevations: Raster<f64>, analysis: TerrainAnalysis, options: AnalysisOptions) -> Self {
+    Self { elevations, analysis, options, stream_orders: RefCell::default(), overhead_hazard: RefCell::default() }
+  }
+
+  /// Stack of layers from `compute_azimuths`, with runout zones all zero if not given; curvatures and ATES
+  /// ratings are recomputed
+  pub fn from_layers(
+    elevations: Raster<f64>,
+    azimuths: Raster<f64>,
+    gradients: Raster<f64>,
+    runout_zones: Option<Raster<f64>>,
+  ) -> Result<Self, PathfinderError> {
+    let runout_zones: Raster<f64> = runout_zones.unwrap_or_else(|| elevations.like(0.0));
+    for (layer, raster) in [("azimuths", &azimuths), ("gradients", &gradients), ("runout zones", &runout_zones)] {
+      if !elevations.same_grid(raster) {
+        return Err(PathfinderError::GridMismatch { layer });
+      }
+    }
+    let curvatures: Curvatures = compute_curvatures(&elevations);
+    let ates_ratings: Raster<f64> = rate_terrain(&gradients, &runout_zones, &curvatures);
+    Ok(Self::from_analysis(
+      elevations,
+      TerrainAnalysis { azimuths, gradients, runout_zones, curvatures, ates_ratings },
+      AnalysisOptions::default(),
+    ))
+  }
+
+  /// Recompute the derived layers for a new set of excluded aspects
+  pub fn reanalyze(&mut self, excluded_aspects: &[Aspect]) {
+    self.analysis = analyze_terrain_with_options(&self.elevations, excluded_aspects, &self.options);
+  }
+
+  /// Switch to `options` and recompute the layers they affect for `excluded_aspects`: all of them for a new slope
+  /// algorithm, otherwise only the runout zones and ATES ratings. The overhead hazard is retraced when next asked for.
+  pub fn update_options(&mut self, options: AnalysisOptions, excluded_aspects: &[Aspect]) {
+    let new_algorithm: bool = options.algorithm != self.options.algorithm;
+    self.options = options;
+    self.overhead_hazard.get_mut().take();
+    if new_algorithm {
+      self.reanalyze(excluded_aspects);
+    } else {
+      self.update_runout_zones(excluded_aspects);
+    }
+  }
+
+  /// Recompute only the runout zones and the ATES ratings that depend on them for a new set of excluded
+  /// aspects, keeping the azimuths, gradients and curvatures
+  pub fn update_runout_zones(&mut self, excluded_aspects: &[Aspect]) {
+    let analysis: &mut TerrainAnalysis = &mut self.analysis;
+    analysis.runout_zones = compute_runout_zones(
+      &self.elevations,
+      &analysis.azimuths,
+      &analysis.gradients,
+      excluded_aspects,
+      self.options.runout_model,
+      &self.options.runout_config,
+    );
+    analysis.ates_ratings = rate_terrain(&analysis.gradients, &analysis.runout_zones, &analysis.curvatures);
+  }
+
+  /// Layer values at the pixel containing `coordinate`, or `None` outside the raster or on a void
+  pub fn sample(&self, coordinate: Coordinate) -> Option<TerrainSample> {
+    let (x, y) = self.elevations.coord_to_pixel(coordinate)?;
+    if self.elevations.is_nodata(x, y) {
+      return None;
+    }
+    let azimuth: f64 = self.analysis.azimuths[(x, y)];
+    let gradient: f64 = self.analysis.gradients[(x, y)];
+    let curvatures: &Curvatures = &self.analysis.curvatures;
+    Some(TerrainSample {
+      elevation: self.elevations[(x, y)],
+      azimuth,
+      aspect: Aspect::from_azimuth(azimuth),
+      gradient,
+      runout: self.analysis.runout_zones[(x, y)],
+      profile_curvature: curvatures.profile[(x, y)],
+      plan_curvature: curvatures.plan[(x, y)],
+      total_curvature: curvatures.total[(x, y)],
+      slope_class: SlopeClass::from_gradient(gradient),
+      ates_rating: AtesRating::from_code(self.analysis.ates_ratings[(x, y)]),
+    })
+  }
+
+  /// Strahler orders of the streams draining at least `threshold_area` m², extracted from the elevations the
+  /// first time each threshold is asked for
+  pub fn stream_orders(&self, threshold_area: f64) -> Rc<Raster<f64>> {
+    self
+      .stream_orders
+      .borrow_mut()
+      .entry(threshold_area.to_bits())
+      .or_insert_with(|| Rc::new(extract_stream_network(&self.elevations, threshold_area).stream_orders))
+      .clone()
+  }
+
+  /// Start zones on the `excluded_aspects` above every cell, within the runout reach set by this stack's options.
+  /// Traced once and kept until asked for with other aspects or options.
+  pub fn overhead_hazard(&self, excluded_aspects: &[Aspect]) -> Rc<OverheadHazard> {
+    let mut cached = self.overhead_hazard.borrow_mut();
+    match cached.as_ref() {
+      Some((aspects, options, hazard)) if aspects == excluded_aspects && *options == self.options => hazard.clone(),
+      _ => {
+        let hazard: Rc<OverheadHazard> = Rc::new(OverheadHazard::new(
+          &self.elevations,
+          &self.analysis.azimuths,
+          &self.analysis.gradients,
+          excluded_aspects,
+          self.options.runout_model,
+          &self.options.runout_config,
+        ));
+        *cached = Some((excluded_aspects.to_vec(), self.options, hazard.clone()));
+        hazard
+      }
+    }
+  }
+
+  /// Start zones on the `excluded_aspects` that can reach each point of `path`
+  pub fn overhead_exposure(&self, path: &[PathPoint], excluded_aspects: &[Aspect]) -> Vec<OverheadExposure> {
+    self.overhead_hazard(excluded_aspects).along(path)
+  }
+
+  /// `find_path` across this stack's layers, treating runout zones as impassable if the options avoid them.
+  /// Stream orders come from `stream_orders`, and start zones above from `overhead_hazard` on the options'
+  /// excluded aspects, when the options need them.
+  pub fn find_path(
+    &self,
+    start: Coordinate,
+    end: Coordinate,
+    options: &PathOptions,
+    on_explore: Option<ExplorationCallback>,
+  ) -> Result<Vec<PathPoint>, PathfinderError> {
+    let stream_orders: Option<Rc<Raster<f64>>> =
+      options.uses_streams().then(|| self.stream_orders(options.stream_threshold_area));
+    let overhead: Option<Rc<OverheadHazard>> =
+      (options.overhead_exposure_cost > 0.0).then(|| self.overhead_hazard(&options.excluded_aspects));
+    let layers = RouteLayers {
+      runout_zones: Some(&self.analysis.runout_zones),
+      ates_ratings: Some(&self.analysis.ates_ratings),
+      stream_orders: stream_orders.as_deref(),
+      overhead: overhead.as_deref(),
+      ..RouteLayers::new(&self.analysis.azimuths, &self.analysis.gradients)
+    };
+    find_path(&self.elevations, layers, start, end, options, on_explore)
+  }
+
+  pub fn to_geotiff(&self, options: &GeoTiffOptions) -> Result<Vec<u8>, PathfinderError> {
+    serialize_bands_to_geotiff(
+      &[
+        (ELEVATIONS, &self.elevations),
+        (AZIMUTHS, &self.analysis.azimuths),
+        (GRADIENTS, &self.analysis.gradients),
+        (RUNOUT_ZONES, &self.analysis.runout_zones),
+        (PROFILE_CURVATURES, &self.analysis.curvatures.profile),
+        (PLAN_CURVATURES, &self.analysis.curvatures.plan),
+        (TOTAL_CURVATURES, &self.analysis.curvatures.total),
+        (ATES_RATINGS, &self.analysis.ates_ratings),
+      ],
+      options,
+    )
+  }
+
+  /// Read a stack written by `to_geotiff`; bands are matched by name, not position.
+  /// Curvatures and ATES ratings are recomputed if the stack predates them.
+  pub fn from_geotiff<R: Read + Seek + Send>(reader: R) -> Result<Self, PathfinderError> {
+    let mut bands: Vec<(String, Raster<f64>)> = read_geotiff_bands(reader)?;
+    let mut take = |band: &'static str| -> Result<Raster<f64>, PathfinderError> {
+      let index: usize = bands
+        .iter()
+        .position(|(name, _)| name == band)
+        .ok_or(PathfinderError::MissingBand { band })?;
+      Ok(bands.swap_remove(index).1)
+    };
+
+    let elevations: Raster<f64> = take(ELEVATIONS)?;
+    let azimuths: Raster<f64> = take(AZIMUTHS)?;
+    let gradients: Raster<f64> = take(GRADIENTS)?;
+    let runout_zones: Raster<f64> = take(RUNOUT_ZONES)?;
+    let curvatures: Curvatures = match (take(PROFILE_CURVATURES), take(PLAN_CURVATURES), take(TOTAL_CURVATURES)) {
+      (Ok(profile), Ok(plan), Ok(total)) => Curvatures { profile, plan, total },
+      _ => compute_curvatures(&elevations),
+    };
+    let ates_ratings: Raster<f64> =
+      take(ATES_RATINGS).unwrap_or_else(|_| rate_terrain(&gradients, &runout_zones, &curvatures));
+
+    Ok(Self::from_analysis(
+      elevations,
+      TerrainAnalysis { azimuths, gradients, runout_zones, curvatures, ates_ratings },
+      AnalysisOptions::default(),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::raster::{CrsInfo, GeoTransform};
+  use std::io::Cursor;
+
+  /// 12x12 valley of 30 m pixels draining south, with steep flanks facing east and west
+  fn valley() -> Raster<f64> {
+    let data: Vec<f64> = (0..144)
+      .map(|index| {
+        let (x, y) = ((index % 12) as f64, (index / 12) as f64);
+        1000.0 + 20.0 * (x - 5.5).abs() - 5.0 * y
+      })
+      .collect();
+    let transform = GeoTransform::new([500_000.0, 4_000_000.0], [30.0, -30.0]);
+    Raster::new(data, 12, 12, transform, CrsInfo::default())
+  }
+
+  #[test]
+  fn stack_round_trips_through_a_geotiff() {
+    let stack = TerrainStack::new(valley(), &[Aspect::East]);
+    let bytes: Vec<u8> = stack.to_geotiff(&GeoTiffOptions::compressed()).unwrap();
+    let read: TerrainStack = TerrainStack::from_geotiff(Cursor::new(bytes)).unwrap();
+
+    let as_f32 = |raster: &Raster<f64>| -> Vec<f32> { raster.data().iter().map(|&value| value as f32).collect() };
+    assert_eq!(read.elevations, stack.elevations);
+    assert_eq!(as_f32(&read.analysis.azimuths), as_f32(&stack.analysis.azimuths));
+    assert_eq!(as_f32(&read.analysis.gradients), as_f32(&stack.analysis.gradients));
+    assert_eq!(as_f32(&read.analysis.runout_zones), as_f32(&stack.analysis.runout_zones));
+    assert_eq!(as_f32(&read.analysis.curvatures.plan), as_f32(&stack.analysis.curvatures.plan));
+    assert_eq!(read.analysis.ates_ratings, stack.analysis.ates_ratings);
+  }
+
+  #[test]
+  fn stream_orders_are_extracted_once_per_threshold() {
+    let stack = TerrainStack::new(valley(), &[]);
+    assert!(Rc::ptr_eq(&stack.stream_orders(5000.0), &stack.stream_orders(5000.0)));
+    assert!(!Rc::ptr_eq(&stack.stream_orders(5000.0), &stack.stream_orders(20_000.0)));
+  }
+
+  #[test]
+  fn overhead_hazard_is_traced_once_per_set_of_aspects() {
+    let stack = TerrainStack::new(valley(), &[]);
+    assert!(Rc::ptr_eq(&stack.overhead_hazard(&[Aspect::East]), &stack.overhead_hazard(&[Aspect::East])));
+    assert!(!Rc::ptr_eq(&stack.overhead_hazard(&[Aspect::East]), &stack.overhead_hazard(&[Aspect::West])));
+  }
+}
@@ -0,0 +1,702 @@
+use std::io::Cursor;
+
+use geojson::{GeoJson, Geometry, Value};
+use georaster::Coordinate;
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+  analyze_slopes, classify_slopes, compute_curvatures, compute_hillshade, compute_runout_zones,
+  delineate_watershed, extract_stream_network_with_routing, fill_depressions, is_raw_raster, path_to_geojson,
+  path_to_geojson_with_exposure, rate_terrain, read_geotiff, read_raw, serialize_to_geotiff_with_options,
+  serialize_to_raw, streams_to_geojson, watershed_to_geojson, AnalysisOptions, Aspect, Curvatures,
+  ExplorationCallback, FlowRouting, GeoTiffOptions, HillshadeOptions, PathOptions, PathfinderError, Raster,
+  RunoutConfig, RunoutModel, SlopeAlgorithm, SlopeAnalysis, StreamNetwork, TerrainStack, Watershed,
+  DEFAULT_ALPHA_ANGLE, DEFAULT_STREAM_THRESHOLD_AREA,
+};
+
+/// Convert to a JS `Error` whose `code` property carries `PathfinderError::code`
+impl From<PathfinderError> for JsValue {
+  fn from(error: PathfinderError) -> Self {
+    let js_error = js_sys::Error::new(&error.to_string());
+    js_error.set_name("PathfinderError");
+    let _ = js_sys::Reflect::set(&js_error, &"code".into(), &error.code().into());
+    js_error.into()
+  }
+}
+
+// Initialize panic hook for better error messages in browser console
+#[wasm_bindgen]
+pub fn init() {
+  console_error_panic_hook::set_once();
+}
+
+/// Elevations and the layers routing needs, each as a GeoTIFF or, from `compute_azimuths_raw`, a raw raster
+/// buffer. Each `take_*` method moves its buffer out to a JS `Uint8Array` of its own, copied once out of wasm
+/// memory, and leaves it empty, so take every layer once and then `free` the result.
+#[wasm_bindgen]
+pub struct AzimuthResult {
+  elevations: Vec<u8>,
+  azimuths: Vec<u8>,
+  gradients: Vec<u8>,
+  runout_zones: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl AzimuthResult {
+  pub fn take_elevations(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.elevations)
+  }
+
+  pub fn take_azimuths(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.azimuths)
+  }
+
+  pub fn take_gradients(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.gradients)
+  }
+
+  pub fn take_runout_zones(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.runout_zones)
+  }
+}
+
+/// Surface curvatures, slope classes and ATES ratings from `compute_terrain_classes_rs`, taken once each as in
+/// `AzimuthResult`
+#[wasm_bindgen]
+pub struct TerrainClassResult {
+  profile_curvatures: Vec<u8>,
+  plan_curvatures: Vec<u8>,
+  total_curvatures: Vec<u8>,
+  slope_classes: Vec<u8>,
+  ates_ratings: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl TerrainClassResult {
+  pub fn take_profile_curvatures(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.profile_curvatures)
+  }
+
+  pub fn take_plan_curvatures(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.plan_curvatures)
+  }
+
+  pub fn take_total_curvatures(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.total_curvatures)
+  }
+
+  pub fn take_slope_classes(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.slope_classes)
+  }
+
+  pub fn take_ates_ratings(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.ates_ratings)
+  }
+}
+
+/// Flow accumulation and stream orders, each as a GeoTIFF or a raw raster buffer, and the stream network as
+/// GeoJSON, from `compute_streams_rs`. Rasters are taken once each as in `AzimuthResult`.
+#[wasm_bindgen]
+pub struct StreamResult {
+  flow_accumulation: Vec<u8>,
+  stream_orders: Vec<u8>,
+  streams: String,
+}
+
+#[wasm_bindgen]
+impl StreamResult {
+  pub fn take_flow_accumulation(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.flow_accumulation)
+  }
+
+  pub fn take_stream_orders(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.stream_orders)
+  }
+
+  /// FeatureCollection of LineStrings with a Strahler `order` property
+  #[wasm_bindgen(getter)]
+  pub fn streams(&self) -> String {
+    self.streams.clone()
+  }
+}
+
+/// Watershed mask as a GeoTIFF or a raw raster buffer, taken once as in `AzimuthResult`, its outline as GeoJSON
+/// and its area, from `delineate_watershed_rs`
+#[wasm_bindgen]
+pub struct WatershedResult {
+  mask: Vec<u8>,
+  outline: String,
+  area: f64,
+}
+
+#[wasm_bindgen]
+impl WatershedResult {
+  pub fn take_mask(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.mask)
+  }
+
+  /// FeatureCollection of one Polygon or MultiPolygon with an `area` property
+  #[wasm_bindgen(getter)]
+  pub fn outline(&self) -> String {
+    self.outline.clone()
+  }
+
+  /// Contributing area in m²
+  #[wasm_bindgen(getter)]
+  pub fn area(&self) -> f64 {
+    self.area
+  }
+}
+
+/// Excluded aspects from an array of lowercase names such as `north`, or none if omitted
+fn parse_excluded_aspects(excluded_aspects: JsValue) -> Result<Vec<Aspect>, PathfinderError> {
+  if excluded_aspects.is_undefined() || excluded_aspects.is_null() {
+    return Ok(vec![]);
+  }
+  serde_wasm_bindgen::from_value(excluded_aspects)
+    .map_err(|e| PathfinderError::InvalidExcludedAspects { message: e.to_string() })
+}
+
+/// `SlopeAlgorithm` by kebab-case name, or the default if omitted
+fn parse_algorithm(algorithm: Option<String>) -> Result<SlopeAlgorithm, PathfinderError> {
+  algorithm.map_or(Ok(SlopeAlgorithm::default()), |name| name.parse())
+}
+
+/// `RunoutConfig` from an object with any of its snake_case fields, or the defaults if omitted
+fn parse_runout_config(runout_config: JsValue) -> Result<RunoutConfig, PathfinderError> {
+  if runout_config.is_undefined() || runout_config.is_null() {
+    return Ok(RunoutConfig::default());
+  }
+  serde_wasm_bindgen::from_value(runout_config)
+    .map_err(|e| PathfinderError::InvalidRunoutConfig { message: e.to_string() })
+}
+
+/// Runout model named `d8` or `alpha-angle`, the latter reaching `alpha_angle` degrees, or else the current
+/// angle or `DEFAULT_ALPHA_ANGLE`. Without a name, a given `alpha_angle` selects the alpha-angle model and
+/// `current` stands otherwise.
+fn parse_runout_model(
+  runout_model: Option<String>,
+  alpha_angle: Option<f64>,
+  current: RunoutModel,
+) -> Result<RunoutModel, PathfinderError> {
+  let current_alpha: Option<f64> = match current {
+    RunoutModel::AlphaAngle { alpha } => Some(alpha),
+    RunoutModel::D8Trace => None,
+  };
+  match runout_model.as_deref().map(str::to_lowercase).as_deref() {
+    Some("d8") => Ok(RunoutModel::D8Trace),
+    Some("alpha-angle") => RunoutModel::alpha_angle(alpha_angle.or(current_alpha).unwrap_or(DEFAULT_ALPHA_ANGLE)),
+    Some(_) => Err(PathfinderError::UnknownRunoutModel { name: runout_model.unwrap_or_default() }),
+    None => alpha_angle.map_or(Ok(current), RunoutModel::alpha_angle),
+  }
+}
+
+/// `AnalysisOptions` with the algorithm named as in `parse_algorithm`, using the alpha-angle runout model if
+/// `alpha_angle` (degrees) is given and D8 tracing otherwise, tuned by `runout_config`
+fn analysis_options(
+  algorithm: Option<String>,
+  alpha_angle: Option<f64>,
+  runout_config: JsValue,
+) -> Result<AnalysisOptions, PathfinderError> {
+  Ok(AnalysisOptions {
+    algorithm: parse_algorithm(algorithm)?,
+    runout_model: alpha_angle.map_or(Ok(RunoutModel::default()), RunoutModel::alpha_angle)?,
+    runout_config: parse_runout_config(runout_config)?,
+  })
+}
+
+/// `HillshadeOptions` with any omitted argument left at its default; the algorithm is named as in
+/// `parse_algorithm` but defaults to `horn`
+fn hillshade_options(
+  azimuth: Option<f64>,
+  altitude: Option<f64>,
+  multidirectional: Option<bool>,
+  algorithm: Option<String>,
+) -> Result<HillshadeOptions, PathfinderError> {
+  let defaults = HillshadeOptions::default();
+  Ok(HillshadeOptions {
+    azimuth: azimuth.unwrap_or(defaults.azimuth),
+    altitude: altitude.unwrap_or(defaults.altitude),
+    multidirectional: multidirectional.unwrap_or(defaults.multidirectional),
+    algorithm: algorithm.map_or(Ok(defaults.algorithm), |name| name.parse())?,
+    ..defaults
+  })
+}
+
+fn parse_point_to_coordinate(point_str: &str) -> Result<Coordinate, PathfinderError> {
+  let geojson: GeoJson = point_str
+    .parse()
+    .map_err(|e: geojson::Error| PathfinderError::InvalidGeoJson { message: e.to_string() })?;
+
+  match geojson {
+    GeoJson::Geometry(Geometry {
+      value: Value::Point(coords),
+      ..
+    }) if coords.len() >= 2 => Ok(Coordinate::new(coords[1], coords[0])),
+    _ => Err(PathfinderError::InvalidGeoJson { message: "expected a Point geometry".to_string() }),
+  }
+}
+
+/// Read a GeoTIFF or raw raster layer, tagging any error with a `layer` property naming the input
+fn read_layer(bytes: &[u8], layer: &str) -> Result<Raster<f64>, JsValue> {
+  let raster: Result<Raster<f64>, PathfinderError> =
+    if is_raw_raster(bytes) { read_raw(bytes) } else { read_geotiff(Cursor::new(bytes)) };
+  raster.map_err(|e| {
+    let js_error = JsValue::from(e);
+    let _ = js_sys::Reflect::set(&js_error, &"layer".into(), &layer.into());
+    js_error
+  })
+}
+
+/// `read_layer` for an optional input, which JS omits by passing an empty buffer
+fn read_optional_layer(bytes: &[u8], layer: &str) -> Result<Option<Raster<f64>>, JsValue> {
+  (!bytes.is_empty()).then(|| read_layer(bytes, layer)).transpose()
+}
+
+/// Compute azimuth and gradient along azimuth for each pixel on a `Vec<f32>` with the named slope `algorithm`
+/// (`horn`, `zevenbergen-thorne`, `evans-young` or the default `sobel5x5`), tracing runout with the alpha-angle
+/// model if `alpha_angle` is given. `runout_config` overrides any of the `RunoutConfig` start zone and runout
+/// parameters.
+#[wasm_bindgen]
+pub fn compute_azimuths(
+  elevations_geotiff: &[u8],
+  excluded_aspects: JsValue,
+  algorithm: Option<String>,
+  alpha_angle: Option<f64>,
+  runout_config: JsValue,
+) -> Result<AzimuthResult, JsValue> {
+  let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+  let options: AnalysisOptions = analysis_options(algorithm, alpha_angle, runout_config)?;
+  let elevations: Raster<f64> = read_layer(elevations_geotiff, "elevations")?;
+  if elevations.crs().geo_keys.is_empty() {
+    return Err(PathfinderError::MissingGeoKeys.into());
+  }
+
+  let SlopeAnalysis { azimuths, gradients, runout_zones } = analyze_slopes(&elevations, &excluded_aspects, &options);
+
+  // Serialize all rasters to compressed, tiled GeoTIFFs to keep the cache and worker messages small
+  let options: GeoTiffOptions = GeoTiffOptions::compressed();
+  Ok(AzimuthResult {
+    elevations: serialize_to_geotiff_with_options(&elevations, &options)?,
+    azimuths: serialize_to_geotiff_with_options(&azimuths, &options)?,
+    gradients: serialize_to_geotiff_with_options(&gradients, &options)?,
+    runout_zones: serialize_to_geotiff_with_options(&runout_zones, &options)?,
+  })
+}
+
+/// `compute_azimuths` returning raw raster buffers instead of GeoTIFFs, skipping TIFF encoding here and
+/// decoding in `find_path_rs`. Each sample array can be viewed in JS as a `Float32Array` without copying.
+#[wasm_bindgen]
+pub fn compute_azimuths_raw(
+  elevations_geotiff: &[u8],
+  excluded_aspects: JsValue,
+  algorithm: Option<String>,
+  alpha_angle: Option<f64>,
+  runout_config: JsValue,
+) -> Result<AzimuthResult, JsValue> {
+  let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+  let options: AnalysisOptions = analysis_options(algorithm, alpha_angle, runout_config)?;
+  let elevations: Raster<f64> = read_layer(elevations_geotiff, "elevations")?;
+  if elevations.crs().geo_keys.is_empty() {
+    return Err(PathfinderError::MissingGeoKeys.into());
+  }
+
+  let SlopeAnalysis { azimuths, gradients, runout_zones } = analyze_slopes(&elevations, &excluded_aspects, &options);
+  Ok(AzimuthResult {
+    elevations: serialize_to_raw(&elevations),
+    azimuths: serialize_to_raw(&azimuths),
+    gradients: serialize_to_raw(&gradients),
+    runout_zones: serialize_to_raw(&runout_zones),
+  })
+}
+
+/// Profile, plan and total curvatures, slope classes and ATES ratings from the elevation, gradient and runout
+/// layers of `compute_azimuths` or `compute_azimuths_raw`, for views that show them; rated as if there were no
+/// runout if that buffer is empty. Layers come back in the elevations' encoding.
+#[wasm_bindgen]
+pub fn compute_terrain_classes_rs(
+  elevations_buffer: &[u8],
+  gradients_buffer: &[u8],
+  runout_zones_buffer: &[u8],
+) -> Result<TerrainClassResult, JsValue> {
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  let gradients: Raster<f64> = read_layer(gradients_buffer, "gradients")?;
+  let runout_zones: Raster<f64> =
+    read_optional_layer(runout_zones_buffer, "runout zones")?.unwrap_or_else(|| elevations.like(0.0));
+  for (layer, raster) in [("gradients", &gradients), ("runout zones", &runout_zones)] {
+    if !elevations.same_grid(raster) {
+      return Err(PathfinderError::GridMismatch { layer }.into());
+    }
+  }
+
+  let curvatures: Curvatures = compute_curvatures(&elevations);
+  let slope_classes: Raster<f64> = classify_slopes(&gradients);
+  let ates_ratings: Raster<f64> = rate_terrain(&gradients, &runout_zones, &curvatures);
+  if is_raw_raster(elevations_buffer) {
+    return Ok(TerrainClassResult {
+      profile_curvatures: serialize_to_raw(&curvatures.profile),
+      plan_curvatures: serialize_to_raw(&curvatures.plan),
+      total_curvatures: serialize_to_raw(&curvatures.total),
+      slope_classes: serialize_to_raw(&slope_classes),
+      ates_ratings: serialize_to_raw(&ates_ratings),
+    });
+  }
+  let geotiff_options: GeoTiffOptions = GeoTiffOptions::compressed();
+  Ok(TerrainClassResult {
+    profile_curvatures: serialize_to_geotiff_with_options(&curvatures.profile, &geotiff_options)?,
+    plan_curvatures: serialize_to_geotiff_with_options(&curvatures.plan, &geotiff_options)?,
+    total_curvatures: serialize_to_geotiff_with_options(&curvatures.total, &geotiff_options)?,
+    slope_classes: serialize_to_geotiff_with_options(&slope_classes, &geotiff_options)?,
+    ates_ratings: serialize_to_geotiff_with_options(&ates_ratings, &geotiff_options)?,
+  })
+}
+
+/// Recompute only the runout zones for new `excluded_aspects` from the elevation, azimuth and gradient layers
+/// of `compute_azimuths` or `compute_azimuths_raw`, skipping the slope convolution and curvatures. The layer
+/// comes back in the elevations' encoding: a raw raster buffer for raw input, a compressed GeoTIFF otherwise.
+/// Views that show ATES ratings rerate with `compute_terrain_classes_rs`, or keep a `Terrain`.
+#[wasm_bindgen]
+pub fn compute_runout_zones_rs(
+  elevations_buffer: &[u8],
+  azimuths_buffer: &[u8],
+  gradients_buffer: &[u8],
+  excluded_aspects: JsValue,
+  alpha_angle: Option<f64>,
+  runout_config: JsValue,
+) -> Result<Vec<u8>, JsValue> {
+  let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+  let options: AnalysisOptions = analysis_options(None, alpha_angle, runout_config)?;
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  let azimuths: Raster<f64> = read_layer(azimuths_buffer, "azimuths")?;
+  let gradients: Raster<f64> = read_layer(gradients_buffer, "gradients")?;
+  for (layer, raster) in [("azimuths", &azimuths), ("gradients", &gradients)] {
+    if !elevations.same_grid(raster) {
+      return Err(PathfinderError::GridMismatch { layer }.into());
+    }
+  }
+
+  let runout_zones: Raster<f64> = compute_runout_zones(
+    &elevations,
+    &azimuths,
+    &gradients,
+    &excluded_aspects,
+    options.runout_model,
+    &options.runout_config,
+  );
+  if is_raw_raster(elevations_buffer) {
+    return Ok(serialize_to_raw(&runout_zones));
+  }
+  Ok(serialize_to_geotiff_with_options(&runout_zones, &GeoTiffOptions::compressed())?)
+}
+
+/// Compute the derived layers and return them with the elevations as one multi-band GeoTIFF
+/// with bands named as in `TerrainStack`
+#[wasm_bindgen]
+pub fn compute_terrain_stack(
+  elevations_geotiff: &[u8],
+  excluded_aspects: JsValue,
+  algorithm: Option<String>,
+  alpha_angle: Option<f64>,
+  runout_config: JsValue,
+) -> Result<Vec<u8>, JsValue> {
+  let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+  let options: AnalysisOptions = analysis_options(algorithm, alpha_angle, runout_config)?;
+  let elevations: Raster<f64> = read_layer(elevations_geotiff, "elevations")?;
+  if elevations.crs().geo_keys.is_empty() {
+    return Err(PathfinderError::MissingGeoKeys.into());
+  }
+
+  Ok(TerrainStack::with_options(elevations, &excluded_aspects, options).to_geotiff(&GeoTiffOptions::compressed())?)
+}
+
+/// Shaded relief of a DEM (GeoTIFF or raw raster) as a compressed GeoTIFF aligned with its derived layers,
+/// lit from `azimuth` (default 315°) at `altitude` (default 45°) or from several directions if `multidirectional`
+#[wasm_bindgen]
+pub fn compute_hillshade_rs(
+  elevations_buffer: &[u8],
+  azimuth: Option<f64>,
+  altitude: Option<f64>,
+  multidirectional: Option<bool>,
+  algorithm: Option<String>,
+) -> Result<Vec<u8>, JsValue> {
+  let options: HillshadeOptions = hillshade_options(azimuth, altitude, multidirectional, algorithm)?;
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  Ok(serialize_to_geotiff_with_options(&compute_hillshade(&elevations, &options), &GeoTiffOptions::compressed())?)
+}
+
+/// Upslope area, Strahler stream orders and stream lines of a DEM, counting drainages of at least
+/// `threshold_area` m² (default 0.1 km²) as streams, with flow accumulated by the named `routing` (`d8`,
+/// `d-infinity` or `mfd`, default `d8`). Rasters come back in the elevations' encoding.
+#[wasm_bindgen]
+pub fn compute_streams_rs(
+  elevations_buffer: &[u8],
+  threshold_area: Option<f64>,
+  routing: Option<String>,
+) -> Result<StreamResult, JsValue> {
+  let routing: FlowRouting = routing.map_or(Ok(FlowRouting::default()), |name| name.parse())?;
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  let network: StreamNetwork = extract_stream_network_with_routing(
+    &elevations,
+    threshold_area.unwrap_or(DEFAULT_STREAM_THRESHOLD_AREA),
+    routing,
+  );
+  let streams: String = streams_to_geojson(&network);
+
+  if is_raw_raster(elevations_buffer) {
+    return Ok(StreamResult {
+      flow_accumulation: serialize_to_raw(&network.flow_accumulation),
+      stream_orders: serialize_to_raw(&network.stream_orders),
+      streams,
+    });
+  }
+  let geotiff_options: GeoTiffOptions = GeoTiffOptions::compressed();
+  Ok(StreamResult {
+    flow_accumulation: serialize_to_geotiff_with_options(&network.flow_accumulation, &geotiff_options)?,
+    stream_orders: serialize_to_geotiff_with_options(&network.stream_orders, &geotiff_options)?,
+    streams,
+  })
+}
+
+/// Area of a DEM draining through `pour_point`, a GeoJSON Point, along D8 flow directions; with
+/// `snap_distance`, the pour point first moves to the cell of greatest flow accumulation within that many
+/// metres. The mask comes back in the elevations' encoding.
+#[wasm_bindgen]
+pub fn delineate_watershed_rs(
+  elevations_buffer: &[u8],
+  pour_point: &str,
+  snap_distance: Option<f64>,
+) -> Result<WatershedResult, JsValue> {
+  let pour_point: Coordinate = parse_point_to_coordinate(pour_point)?;
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  let watershed: Watershed = delineate_watershed(&elevations, pour_point, snap_distance)?;
+  let mask: Vec<u8> = if is_raw_raster(elevations_buffer) {
+    serialize_to_raw(&watershed.mask)
+  } else {
+    serialize_to_geotiff_with_options(&watershed.mask, &GeoTiffOptions::compressed())?
+  };
+  Ok(WatershedResult { mask, outline: watershed_to_geojson(&watershed), area: watershed.area })
+}
+
+/// A DEM (GeoTIFF or raw raster) with its depressions filled, as a compressed GeoTIFF; filled cells rise by
+/// `epsilon` metres per cell if given, and are left level otherwise
+#[wasm_bindgen]
+pub fn fill_depressions_rs(elevations_buffer: &[u8], epsilon: Option<f64>) -> Result<Vec<u8>, JsValue> {
+  let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+  Ok(serialize_to_geotiff_with_options(&fill_depressions(&elevations, epsilon), &GeoTiffOptions::compressed())?)
+}
+
+/// `PathOptions` from an object with any of its snake_case fields, or the defaults if omitted
+fn parse_path_options(path_options: JsValue) -> Result<PathOptions, PathfinderError> {
+  if path_options.is_undefined() || path_options.is_null() {
+    return Ok(PathOptions::default());
+  }
+  serde_wasm_bindgen::from_value(path_options)
+    .map_err(|e| PathfinderError::InvalidPathOptions { message: e.to_string() })
+}
+
+/// Route between two GeoJSON points across a stack's layers, forwarding the search frontier to
+/// `exploration_callback`. Points carry the start zones above them if the options ask for it.
+fn route_geojson(
+  stack: &TerrainStack,
+  start: &str,
+  end: &str,
+  options: &PathOptions,
+  exploration_callback: Option<Function>,
+) -> Result<String, JsValue> {
+  let start: Coordinate = parse_point_to_coordinate(start)?;
+  let end: Coordinate = parse_point_to_coordinate(end)?;
+
+  // Forward the search frontier to JS as an array of [lon, lat] pairs
+  let mut on_explore = exploration_callback.map(|callback| {
+    move |frontier: &[Coordinate]| {
+      let arr = js_sys::Array::new();
+      for coordinate in frontier {
+        let point = js_sys::Array::new();
+        point.push(&JsValue::from_f64(coordinate.x));
+        point.push(&JsValue::from_f64(coordinate.y));
+        arr.push(&point);
+      }
+      let _ = callback.call1(&JsValue::NULL, &arr);
+    }
+  });
+
+  let path = stack.find_path(start, end, options, on_explore.as_mut().map(|f| f as ExplorationCallback))?;
+  if !options.report_overhead_exposure {
+    return Ok(path_to_geojson(&path));
+  }
+  Ok(path_to_geojson_with_exposure(&path, &stack.overhead_exposure(&path, &options.excluded_aspects)))
+}
+
+/// Route across layers from `compute_azimuths` or `compute_azimuths_raw`; each buffer may be either encoding, and
+/// an empty runout zones buffer routes without them. `path_options` sets any of the snake_case `PathOptions`
+/// fields, e.g. `{ max_gradient: 0.5, excluded_aspects: ["north"], max_ates_rating: "challenging" }`.
+/// ATES ratings for `max_ates_rating` are derived from these layers, and start zones for `overhead_exposure_cost`
+/// reach as far as the default D8 runout.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn find_path_rs(
+  elevations_buffer: &[u8],
+  azimuths_buffer: &[u8],
+  gradients_buffer: &[u8],
+  runout_zones_buffer: &[u8],
+  start: String,
+  end: String,
+  path_options: JsValue,
+  exploration_callback: Option<Function>,
+) -> Result<String, JsValue> {
+  let options: PathOptions = parse_path_options(path_options)?;
+  let stack: TerrainStack = TerrainStack::from_layers(
+    read_layer(elevations_buffer, "elevations")?,
+    read_layer(azimuths_buffer, "azimuths")?,
+    read_layer(gradients_buffer, "gradients")?,
+    read_optional_layer(runout_zones_buffer, "runout zones")?,
+  )?;
+  route_geojson(&stack, &start, &end, &options, exploration_callback)
+}
+
+/// `find_path_rs` on a terrain stack from `compute_terrain_stack`
+#[wasm_bindgen]
+pub fn find_path_stack_rs(
+  terrain_stack: &[u8],
+  start: String,
+  end: String,
+  path_options: JsValue,
+  exploration_callback: Option<Function>,
+) -> Result<String, JsValue> {
+  let options: PathOptions = parse_path_options(path_options)?;
+  let stack: TerrainStack = TerrainStack::from_geotiff(Cursor::new(terrain_stack))?;
+  route_geojson(&stack, &start, &end, &options, exploration_callback)
+}
+
+/// Elevations and derived layers parsed once and kept in wasm memory, so repeated routing and sampling
+/// queries skip decoding
+#[wasm_bindgen]
+pub struct Terrain {
+  stack: TerrainStack,
+}
+
+#[wasm_bindgen]
+impl Terrain {
+  /// Parse a DEM (GeoTIFF or raw raster) and derive its layers with the named slope `algorithm`, with runout
+  /// zones below `excluded_aspects` traced with the alpha-angle model if `alpha_angle` is given and tuned by
+  /// `runout_config`
+  #[wasm_bindgen(constructor)]
+  pub fn new(
+    elevations_buffer: &[u8],
+    excluded_aspects: JsValue,
+    algorithm: Option<String>,
+    alpha_angle: Option<f64>,
+    runout_config: JsValue,
+  ) -> Result<Terrain, JsValue> {
+    let elevations: Raster<f64> = read_layer(elevations_buffer, "elevations")?;
+    if elevations.crs().geo_keys.is_empty() {
+      return Err(PathfinderError::MissingGeoKeys.into());
+    }
+    let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+    let options: AnalysisOptions = analysis_options(algorithm, alpha_angle, runout_config)?;
+    Ok(Terrain { stack: TerrainStack::with_options(elevations, &excluded_aspects, options) })
+  }
+
+  /// Load a terrain stack from `compute_terrain_stack` or `toStack`
+  #[wasm_bindgen(js_name = fromStack)]
+  pub fn from_stack(terrain_stack: &[u8]) -> Result<Terrain, JsValue> {
+    Ok(Terrain { stack: TerrainStack::from_geotiff(Cursor::new(terrain_stack))? })
+  }
+
+  /// Load layers from `compute_azimuths` or `compute_azimuths_raw`; runout zones are all zero if their buffer is
+  /// empty, and curvatures and ATES ratings are recomputed
+  #[wasm_bindgen(js_name = fromLayers)]
+  pub fn from_layers(
+    elevations_buffer: &[u8],
+    azimuths_buffer: &[u8],
+    gradients_buffer: &[u8],
+    runout_zones_buffer: &[u8],
+  ) -> Result<Terrain, JsValue> {
+    let stack: TerrainStack = TerrainStack::from_layers(
+      read_layer(elevations_buffer, "elevations")?,
+      read_layer(azimuths_buffer, "azimuths")?,
+      read_layer(gradients_buffer, "gradients")?,
+      read_optional_layer(runout_zones_buffer, "runout zones")?,
+    )?;
+    Ok(Terrain { stack })
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn width(&self) -> usize {
+    self.stack.elevations.width()
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn height(&self) -> usize {
+    self.stack.elevations.height()
+  }
+
+  /// Recompute the derived layers with runout zones below a new set of `excluded_aspects`, switching to the
+  /// named slope `algorithm` and to `runout_config` if given. `runout_model` switches between `d8` and
+  /// `alpha-angle` runout; without it, `alpha_angle` degrees switch to the alpha-angle model and the current
+  /// model stands otherwise. Only a new algorithm reruns the slope convolution, and nothing changes if any
+  /// argument is invalid.
+  pub fn analyze(
+    &mut self,
+    excluded_aspects: JsValue,
+    algorithm: Option<String>,
+    alpha_angle: Option<f64>,
+    runout_config: JsValue,
+    runout_model: Option<String>,
+  ) -> Result<(), JsValue> {
+    // Parse everything before touching the stack, so a bad argument leaves it as it was
+    let mut options: AnalysisOptions = self.stack.options;
+    options.runout_model = parse_runout_model(runout_model, alpha_angle, options.runout_model)?;
+    if !runout_config.is_undefined() && !runout_config.is_null() {
+      options.runout_config = parse_runout_config(runout_config)?;
+    }
+    if algorithm.is_some() {
+      options.algorithm = parse_algorithm(algorithm)?;
+    }
+    let excluded_aspects: Vec<Aspect> = parse_excluded_aspects(excluded_aspects)?;
+    self.stack.update_options(options, &excluded_aspects);
+    Ok(())
+  }
+
+  /// Route between two GeoJSON points with `path_options` as in `find_path_rs`. Start zones for
+  /// `overhead_exposure_cost` reach as far as this terrain's runout model allows.
+  pub fn find_path(
+    &self,
+    start: String,
+    end: String,
+    path_options: JsValue,
+    exploration_callback: Option<Function>,
+  ) -> Result<String, JsValue> {
+    let options: PathOptions = parse_path_options(path_options)?;
+    route_geojson(&self.stack, &start, &end, &options, exploration_callback)
+  }
+
+  /// `{ elevation, azimuth, aspect, gradient, runout, profile_curvature, plan_curvature, total_curvature,
+  /// slope_class, ates_rating }` at a longitude/latitude, or `undefined` outside the raster or on a void
+  pub fn sample(&self, lon: f64, lat: f64) -> Result<JsValue, JsValue> {
+    match self.stack.sample(Coordinate::new(lat, lon)) {
+      Some(sample) => Ok(serde_wasm_bindgen::to_value(&sample)?),
+      None => Ok(JsValue::UNDEFINED),
+    }
+  }
+
+  /// Shaded relief as a raw raster buffer whose samples can be viewed as a `Float32Array`; arguments as in
+  /// `compute_hillshade_rs`
+  pub fn hillshade(
+    &self,
+    azimuth: Option<f64>,
+    altitude: Option<f64>,
+    multidirectional: Option<bool>,
+    algorithm: Option<String>,
+  ) -> Result<Vec<u8>, JsValue> {
+    let options: HillshadeOptions = hillshade_options(azimuth, altitude, multidirectional, algorithm)?;
+    Ok(serialize_to_raw(&compute_hillshade(&self.stack.elevations, &options)))
+  }
+
+  /// Serialize all layers as a compressed terrain stack GeoTIFF, e.g. for caching
+  #[wasm_bindgen(js_name = toStack)]
+  pub fn to_stack(&self) -> Result<Vec<u8>, JsValue> {
+    Ok(self.stack.to_geotiff(&GeoTiffOptions::compressed())?)
+  }
+}
@@ -5,7 +5,7 @@
  * Sends exploration updates back to main thread for visualization.
  */
 
-import wasmInit, { find_path_rs, compute_azimuths, init as initPanicHook } from '../pathfinder/pkg/pathfinder';
+import wasmInit, { find_path_rs, compute_azimuths_raw, init as initPanicHook } from '../pathfinder/pkg/pathfinder';
 
 // Types for messages
 export interface PathfinderRequest {
@@ -44,6 +44,7 @@ export interface PathResult {
   geojson: string;
 }
 
+// Layers are raw raster buffers (see lib/raw-raster.ts), transferred rather than copied to the main thread
 export interface AzimuthsResult {
   type: 'azimuths_result';
   id: string;
@@ -57,6 +58,7 @@ export interface ErrorResult {
   type: 'error';
   id: string;
   message: string;
+  code?: string; // Stable PathfinderError code from the WASM module, e.g. 'START_OUT_OF_BOUNDS'
 }
 
 export type WorkerRequest = PathfinderRequest | ComputeAzimuthsRequest;
@@ -64,6 +66,16 @@ export type WorkerResponse = ExplorationUpdate | PathResult | AzimuthsResult | E
 
 let wasmInitialized = false;
 
+/**
+ * Extract the PathfinderError code attached to errors thrown by the WASM module
+ */
+function errorCode(error: unknown): string | undefined {
+  if (error && typeof error === 'object' && 'code' in error) {
+    return String((error as { code: unknown }).code);
+  }
+  return undefined;
+}
+
 /**
  * Initialize WASM module in worker
  */
@@ -173,18 +185,21 @@ async function handleFindPath(request: PathfinderRequest): Promise<void> {
       throw new Error('runoutZonesBuffer is detached');
     }
     
+    // Snake_case PathOptions fields; omitted ones keep their Rust defaults
+    const pathOptions: Record<string, unknown> = { excluded_aspects: excludedAspects };
+    if (maxGradient !== null) pathOptions.max_gradient = maxGradient;
+    if (aspectGradientThreshold !== null) pathOptions.aspect_gradient_threshold = aspectGradientThreshold;
+    if (explorationBatchSize !== undefined) pathOptions.exploration_batch_size = explorationBatchSize;
+
     const resultJson = find_path_rs(
       elevationsBuffer,
-      startGeoJson,
-      endGeoJson,
-      maxGradient,
       azimuthsBuffer,
-      excludedAspects,
       gradientsBuffer,
-      aspectGradientThreshold,
-      explorationCallback,
-      explorationBatchSize,
-      runoutZonesBuffer ?? null
+      runoutZonesBuffer ?? new Uint8Array(),
+      startGeoJson,
+      endGeoJson,
+      pathOptions,
+      explorationCallback
     );
     
     console.log('[Worker] Path found, result length:', resultJson.length);
@@ -215,7 +230,8 @@ async function handleFindPath(request: PathfinderRequest): Promise<void> {
     self.postMessage({
       type: 'error',
       id,
-      message
+      message,
+      code: errorCode(error)
     } satisfies ErrorResult);
   }
 }
@@ -244,29 +260,39 @@ async function handleComputeAzimuths(request: ComputeAzimuthsRequest): Promise<v
     await ensureWasmInit();
     console.log('[Worker] WASM initialized for azimuths');
     
-    const result = compute_azimuths(elevationsGeotiff, excludedAspects ?? []);
+    // Raw buffers skip TIFF encoding here and decoding in find_path_rs; each layer is taken out of WASM memory once
+    const result = compute_azimuths_raw(elevationsGeotiff, excludedAspects ?? []);
+    const elevations = result.take_elevations();
+    const azimuths = result.take_azimuths();
+    const gradients = result.take_gradients();
+    const runout_zones = result.take_runout_zones();
+    result.free();
     console.log('[Worker] Azimuths computed:', {
-      elevationsLength: result.elevations.length,
-      azimuthsLength: result.azimuths.length,
-      gradientsLength: result.gradients.length,
-      runoutZonesLength: result.runout_zones?.length
+      elevationsLength: elevations.length,
+      azimuthsLength: azimuths.length,
+      gradientsLength: gradients.length,
+      runoutZonesLength: runout_zones.length
     });
     
-    self.postMessage({
-      type: 'azimuths_result',
-      id,
-      elevations: result.elevations,
-      azimuths: result.azimuths,
-      gradients: result.gradients,
-      runout_zones: result.runout_zones
-    } satisfies AzimuthsResult);
+    self.postMessage(
+      {
+        type: 'azimuths_result',
+        id,
+        elevations,
+        azimuths,
+        gradients,
+        runout_zones
+      } satisfies AzimuthsResult,
+      { transfer: [elevations, azimuths, gradients, runout_zones].map((layer) => layer.buffer as ArrayBuffer) }
+    );
     
   } catch (error) {
     console.error('[Worker] Azimuth computation error:', error);
     self.postMessage({
       type: 'error',
       id,
-      message: error instanceof Error ? error.message : 'Unknown error computing azimuths'
+      message: error instanceof Error ? error.message : 'Unknown error computing azimuths',
+      code: errorCode(error)
     } satisfies ErrorResult);
   }
 }